@@ -0,0 +1,113 @@
+//! Agenda files as set by Emacs' `org-agenda-files`: a list of files and
+//! directories, or the name of a text file with one of them per line.
+//!
+//! As in Emacs, a directory stands for its Org files, not those of its
+//! subdirectories, and names starting with `.` are left out. Blank lines of
+//! the text file are skipped. `~/` is the home directory, and relative paths
+//! are relative to the directory of the text file, or to that of the
+//! configuration for a list.
+use crate::walk::{org_files, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The value of `org-agenda-files`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AgendaFiles {
+    List(Vec<String>),
+    /// A text file with a file or directory per line
+    File(String),
+}
+
+impl AgendaFiles {
+    /// The Org files, in the order of the list, relative paths being relative
+    /// to `base`
+    pub fn paths(&self, base: &Path) -> Result<Vec<PathBuf>, String> {
+        match self {
+            AgendaFiles::List(entries) => entry_paths(entries, base),
+            AgendaFiles::File(file) => {
+                let file = expand_path(file, base);
+                let text = fs::read_to_string(&file)
+                    .map_err(|error| format!("{}: {}", file.display(), error))?;
+                let entries: Vec<&str> = text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .collect();
+                entry_paths(&entries, file.parent().unwrap_or(Path::new("")))
+            }
+        }
+    }
+}
+
+/// The files of the entries of a list, and the Org files of its directories
+fn entry_paths(entries: &[impl AsRef<str>], base: &Path) -> Result<Vec<PathBuf>, String> {
+    let options = WalkOptions {
+        ignore_files: Vec::new(),
+        max_depth: Some(0),
+        ..WalkOptions::default()
+    };
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        let path = expand_path(entry.as_ref(), base);
+        if path.is_dir() {
+            paths.extend(org_files(&[&path], &options)?);
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// A path with `~/` expanded, relative to a directory
+pub fn expand_path(path: &str, base: &Path) -> PathBuf {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    let path = match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    };
+    if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn read_agenda_file_list() {
+        let dir = TempDir::new(
+            "agenda",
+            &[
+                ("inbox.org", ""),
+                ("projects/a.org", ""),
+                ("projects/.b.org", ""),
+                ("projects/sub/c.org", ""),
+                ("agenda-files", "projects\n\n  inbox.org  \n"),
+            ],
+        );
+        let root = dir.root();
+
+        let files = AgendaFiles::File(root.join("agenda-files").to_string_lossy().into_owned());
+        assert_eq!(
+            files.paths(Path::new("/elsewhere")).unwrap(),
+            [root.join("projects/a.org"), root.join("inbox.org")]
+        );
+        let list = AgendaFiles::List(vec!["inbox.org".to_string()]);
+        assert_eq!(list.paths(root).unwrap(), [root.join("inbox.org")]);
+    }
+
+    #[test]
+    fn read_agenda_files_option() {
+        let list: AgendaFiles =
+            serde_json::from_str(r#"["~/org/inbox.org", "~/org/projects"]"#).unwrap();
+        assert!(matches!(list, AgendaFiles::List(entries) if entries.len() == 2));
+        let file: AgendaFiles = serde_json::from_str(r#""~/.agenda_files""#).unwrap();
+        assert_eq!(file, AgendaFiles::File("~/.agenda_files".to_string()));
+    }
+}
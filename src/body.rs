@@ -0,0 +1,381 @@
+//! The body of a headline, i.e. the notes in its section, as the event's
+//! `description`.
+//!
+//! The section is rendered to HTML by orgize's HTML export, or to plain
+//! text. Drawers (LOGBOOK, ...), CLOCK lines, keywords and comments are left
+//! out; the planning line and the properties belong to the title, so they
+//! are not in the section. The HTML is sanitized: export blocks and
+//! snippets, which would pass raw HTML, are left out, and links are written
+//! as such only to web and relative targets: others, like `javascript:`, are
+//! written as their text.
+use crate::markup::{escape_html, is_safe_link};
+use crate::timestamp::format_datetime;
+use orgize::elements::{Element, Link, Timestamp};
+use orgize::export::{DefaultHtmlHandler, HtmlHandler};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Appended to a truncated body
+const ELLIPSIS: &str = "…";
+
+/// What the `description` of the events is made from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DescriptionSource {
+    /// The `DESCRIPTION` properties, else the title
+    #[default]
+    Property,
+    /// The body as HTML, else the `DESCRIPTION` properties
+    Html,
+    /// The body as plain text, else the `DESCRIPTION` properties
+    Plain,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DescriptionOptions {
+    pub source: DescriptionSource,
+    /// Number of characters of text after which the body is cut
+    pub max_length: Option<usize>,
+}
+
+/// Renders the elements of a section as they are visited
+pub struct BodyWriter {
+    html: Option<DefaultHtmlHandler>,
+    output: Vec<u8>,
+    max_length: Option<usize>,
+    /// Characters of text written
+    length: usize,
+    truncated: bool,
+    /// Depth of the elements being left out
+    skipped: usize,
+    /// The plain text is right after a list bullet
+    after_bullet: bool,
+}
+
+impl BodyWriter {
+    /// None for [`DescriptionSource::Property`]
+    pub fn new(options: &DescriptionOptions) -> Option<BodyWriter> {
+        let html = match options.source {
+            DescriptionSource::Property => return None,
+            DescriptionSource::Html => Some(DefaultHtmlHandler),
+            DescriptionSource::Plain => None,
+        };
+        Some(BodyWriter {
+            html,
+            output: Vec::new(),
+            max_length: options.max_length,
+            length: 0,
+            truncated: false,
+            skipped: 0,
+            after_bullet: false,
+        })
+    }
+
+    pub fn start(&mut self, element: &Element) {
+        if self.skipped > 0 || self.truncated || is_left_out(element) {
+            self.skipped += 1;
+            return;
+        }
+        match element {
+            Element::Text { value } => {
+                let value = self.take_text(value);
+                self.write_start(&Element::Text { value });
+            }
+            Element::Verbatim { value } => {
+                let value = self.take_text(value);
+                self.write_start(&Element::Verbatim { value });
+            }
+            Element::Code { value } => {
+                let value = self.take_text(value);
+                self.write_start(&Element::Code { value });
+            }
+            Element::Link(link) => {
+                let text = link.desc.clone().unwrap_or_else(|| link.path.clone());
+                let value = self.take_text(&text);
+                if is_safe_link(&link.path) {
+                    self.write_start(&Element::Link(Link {
+                        path: link.path.clone(),
+                        desc: Some(value),
+                    }));
+                } else {
+                    self.write_start(&Element::Text { value });
+                }
+            }
+            Element::Timestamp(timestamp) => {
+                let text = Cow::Owned(timestamp_text(timestamp));
+                let value = self.take_text(&text);
+                if self.truncated {
+                    // What is left of it is no longer a timestamp
+                    self.write_start(&Element::Text { value });
+                } else {
+                    self.write_start(element);
+                }
+            }
+            _ => self.write_start(element),
+        }
+    }
+
+    pub fn end(&mut self, element: &Element) {
+        if self.skipped > 0 {
+            self.skipped -= 1;
+            return;
+        }
+        match &mut self.html {
+            Some(handler) => {
+                let _ = handler.end(&mut self.output, element);
+            }
+            None => {
+                if let Element::Paragraph { .. } | Element::ListItem(..) = element {
+                    self.new_line();
+                }
+            }
+        }
+    }
+
+    /// The body, None when it is empty
+    pub fn finish(self) -> Option<String> {
+        let text = String::from_utf8_lossy(&self.output);
+        let text = if self.html.is_some() {
+            text.trim().to_string()
+        } else {
+            let lines: Vec<&str> = text
+                .lines()
+                .map(|line| line.trim_end())
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+            lines.join("\n")
+        };
+        Some(text).filter(|text| !text.is_empty())
+    }
+
+    /// The text to write of a value: all of it, or what is left of the
+    /// length with an ellipsis
+    fn take_text<'b>(&mut self, value: &Cow<'b, str>) -> Cow<'b, str> {
+        let length = value.chars().count();
+        let left = match self.max_length {
+            Some(max_length) => max_length.saturating_sub(self.length),
+            None => length,
+        };
+        if length <= left {
+            self.length += length;
+            return value.clone();
+        }
+        self.truncated = true;
+        self.length += left;
+        let mut cut: String = value.chars().take(left).collect();
+        cut.truncate(cut.trim_end().len());
+        cut.push_str(ELLIPSIS);
+        Cow::Owned(cut)
+    }
+
+    fn write_start(&mut self, element: &Element) {
+        if let Some(handler) = &mut self.html {
+            let _ = handler.start(&mut self.output, element);
+            return;
+        }
+        let text = match element {
+            Element::Text { value } | Element::Verbatim { value } | Element::Code { value } => {
+                value.to_string()
+            }
+            Element::Link(link) => link.desc.as_ref().unwrap_or(&link.path).to_string(),
+            Element::Timestamp(timestamp) => timestamp_text(timestamp),
+            Element::ListItem(..) => {
+                self.new_line();
+                self.output.extend_from_slice(b"- ");
+                self.after_bullet = true;
+                return;
+            }
+            Element::Paragraph { .. } => {
+                if !self.after_bullet {
+                    self.new_line();
+                }
+                return;
+            }
+            _ => return,
+        };
+        self.after_bullet = false;
+        self.output.extend_from_slice(text.as_bytes());
+    }
+
+    fn new_line(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with(b"\n") {
+            self.output.push(b'\n');
+        }
+        self.after_bullet = false;
+    }
+}
+
+/// Elements which are not part of the notes, or could carry raw HTML
+fn is_left_out(element: &Element) -> bool {
+    matches!(
+        element,
+        Element::Drawer(..)
+            | Element::Clock(..)
+            | Element::Keyword(..)
+            | Element::Comment(..)
+            | Element::CommentBlock(..)
+            | Element::ExportBlock(..)
+            | Element::Snippet(..)
+            | Element::Macros(..)
+    )
+}
+
+/// A timestamp as written in Org
+fn timestamp_text(timestamp: &Timestamp) -> String {
+    match timestamp {
+        Timestamp::Active { start, .. } => format!("<{}>", format_datetime(start)),
+        Timestamp::Inactive { start, .. } => format!("[{}]", format_datetime(start)),
+        Timestamp::ActiveRange { start, end, .. } => {
+            format!("<{}>--<{}>", format_datetime(start), format_datetime(end))
+        }
+        Timestamp::InactiveRange { start, end, .. } => {
+            format!("[{}]--[{}]", format_datetime(start), format_datetime(end))
+        }
+        Timestamp::Diary { value } => format!("<%%{}>", value),
+    }
+}
+
+/// The `DESCRIPTION` properties joined by line breaks, for the headlines
+/// without a body
+pub fn property_description(
+    properties: impl IntoIterator<Item = (String, String)>,
+    source: DescriptionSource,
+) -> Option<String> {
+    let values: Vec<String> = properties
+        .into_iter()
+        .filter(|(key, _)| key == "DESCRIPTION")
+        .map(|(_, value)| match source {
+            DescriptionSource::Html => escape_html(value.trim()),
+            _ => value.trim().to_string(),
+        })
+        .collect();
+    let separator = match source {
+        DescriptionSource::Html => "<br>",
+        _ => "\n",
+    };
+    Some(values.join(separator)).filter(|description| !description.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(max_length: Option<usize>) -> BodyWriter {
+        BodyWriter::new(&DescriptionOptions {
+            source: DescriptionSource::Plain,
+            max_length,
+        })
+        .unwrap()
+    }
+
+    fn text(value: &str) -> Element<'_> {
+        Element::Text {
+            value: Cow::Borrowed(value),
+        }
+    }
+
+    #[test]
+    fn write_plain_text() {
+        let paragraph = Element::Paragraph { post_blank: 0 };
+        let drawer = Element::Drawer(orgize::elements::Drawer {
+            name: Cow::Borrowed("LOGBOOK"),
+            pre_blank: 0,
+            post_blank: 0,
+        });
+        let mut writer = plain(None);
+        for element in [&paragraph, &text("Agenda:")] {
+            writer.start(element);
+        }
+        writer.end(&text("Agenda:"));
+        writer.end(&paragraph);
+        writer.start(&drawer);
+        writer.start(&text("CLOCK"));
+        writer.end(&text("CLOCK"));
+        writer.end(&drawer);
+        writer.start(&paragraph);
+        writer.start(&text("Budget  "));
+        writer.end(&text("Budget  "));
+        writer.end(&paragraph);
+        assert_eq!(writer.finish().as_deref(), Some("Agenda:\nBudget"));
+
+        assert_eq!(plain(None).finish(), None);
+    }
+
+    #[test]
+    fn truncate_long_bodies() {
+        let mut writer = plain(Some(8));
+        writer.start(&text("Call the "));
+        writer.end(&text("Call the "));
+        writer.start(&text("plumber"));
+        writer.end(&text("plumber"));
+        assert_eq!(writer.finish().as_deref(), Some("Call the…"));
+    }
+
+    #[test]
+    fn write_safe_links_only() {
+        let link = |path| {
+            Element::Link(Link {
+                path: Cow::Borrowed(path),
+                desc: Some(Cow::Borrowed("x<y")),
+            })
+        };
+        let paragraph = Element::Paragraph { post_blank: 0 };
+        let mut writer = BodyWriter::new(&DescriptionOptions {
+            source: DescriptionSource::Html,
+            max_length: None,
+        })
+        .unwrap();
+        writer.start(&paragraph);
+        for path in [
+            "https://example.com",
+            "java\tscript:alert(1)",
+            "java\nscript:alert(1)",
+            "notes.html",
+        ] {
+            writer.start(&link(path));
+            writer.end(&link(path));
+        }
+        writer.end(&paragraph);
+        assert_eq!(
+            writer.finish().as_deref(),
+            Some(concat!(
+                "<p><a href=\"https://example.com\">x&lt;y</a>x&lt;yx&lt;y",
+                "<a href=\"notes.html\">x&lt;y</a></p>"
+            ))
+        );
+    }
+
+    #[test]
+    fn truncate_long_links() {
+        let link = Element::Link(Link {
+            path: Cow::Borrowed("https://example.com"),
+            desc: Some(Cow::Borrowed("the plumber's site")),
+        });
+        let mut writer = plain(Some(12));
+        writer.start(&text("See "));
+        writer.end(&text("See "));
+        writer.start(&link);
+        writer.end(&link);
+        writer.start(&text(" for more"));
+        writer.end(&text(" for more"));
+        assert_eq!(writer.finish().as_deref(), Some("See the plum…"));
+    }
+
+    #[test]
+    fn join_description_properties() {
+        let properties = vec![
+            ("DESCRIPTION".to_string(), "Room <A>".to_string()),
+            ("LOCATION".to_string(), "Office".to_string()),
+            ("DESCRIPTION".to_string(), "2nd floor".to_string()),
+        ];
+        assert_eq!(
+            property_description(properties.clone(), DescriptionSource::Html).as_deref(),
+            Some("Room &lt;A&gt;<br>2nd floor")
+        );
+        assert_eq!(
+            property_description(properties, DescriptionSource::Plain).as_deref(),
+            Some("Room <A>\n2nd floor")
+        );
+    }
+}
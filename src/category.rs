@@ -0,0 +1,141 @@
+//! Categories of headlines and the colors given to them.
+//!
+//! The category is the `CATEGORY` property of the headline or its nearest
+//! ancestor, else the file's `#+CATEGORY:`, else the file name without its
+//! extension, as in Org's agenda. Events can be colored per category or per
+//! file from a palette. The color is picked by a hash of the name, so it
+//! stays the same from one run to the next.
+use crate::color::Color;
+use crate::hash::stable_hash;
+use crate::outline::Outline;
+use crate::settings::FileSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Tableau 10
+pub const DEFAULT_PALETTE: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
+/// What the palette colors are given by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaletteKey {
+    /// No automatic colors
+    #[default]
+    None,
+    Category,
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorOptions {
+    pub palette_by: PaletteKey,
+    /// Colors to pick from, [`DEFAULT_PALETTE`] when empty
+    pub palette: Vec<Color>,
+    /// Colors per category (or file name) instead of the palette's
+    pub fixed: BTreeMap<String, Color>,
+    /// Give events with a background color but no `FC_TXT_COLOR` the most
+    /// readable text color, black or white
+    pub contrast_text: bool,
+}
+
+impl Default for ColorOptions {
+    fn default() -> ColorOptions {
+        ColorOptions {
+            palette_by: PaletteKey::None,
+            palette: Vec::new(),
+            fixed: BTreeMap::new(),
+            contrast_text: true,
+        }
+    }
+}
+
+impl ColorOptions {
+    /// Palette color of a headline with this category in this file
+    pub fn palette_color(&self, category: Option<&str>, file_path: &str) -> Option<String> {
+        let name = match self.palette_by {
+            PaletteKey::None => return None,
+            PaletteKey::Category => category?.to_string(),
+            PaletteKey::File => file_name(file_path)?,
+        };
+        if let Some(color) = self.fixed.get(&name) {
+            return Some(color.to_string());
+        }
+        let index = |len: usize| (stable_hash(&name) % len as u64) as usize;
+        if self.palette.is_empty() {
+            Some(DEFAULT_PALETTE[index(DEFAULT_PALETTE.len())].to_string())
+        } else {
+            Some(self.palette[index(self.palette.len())].to_string())
+        }
+    }
+}
+
+/// File name without its extension
+fn file_name(file_path: &str) -> Option<String> {
+    Path::new(file_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .filter(|stem| !stem.is_empty())
+}
+
+/// Category of the current headline of an outline, or of the text before
+/// the first headline without one
+pub fn category(outline: &Outline, settings: &FileSettings, file_path: &str) -> Option<String> {
+    // CATEGORY is always inherited in Org
+    let property = outline
+        .current()
+        .into_iter()
+        .chain(outline.ancestors().iter().rev().copied())
+        .find_map(|title| {
+            title
+                .properties
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case("CATEGORY"))
+                .map(|(_, value)| value.trim().to_string())
+                .last()
+                .filter(|value| !value.is_empty())
+        });
+    property
+        .or_else(|| settings.category.clone())
+        .or_else(|| file_name(file_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_palette_colors() {
+        let options = ColorOptions {
+            palette_by: PaletteKey::Category,
+            ..ColorOptions::default()
+        };
+        let work = options.palette_color(Some("work"), "");
+        assert!(work.is_some());
+        assert_eq!(options.palette_color(Some("work"), "other.org"), work);
+        assert_eq!(options.palette_color(None, "work.org"), None);
+
+        let options = ColorOptions {
+            palette_by: PaletteKey::File,
+            palette: vec!["black".parse().unwrap()],
+            fixed: BTreeMap::from([("home".to_string(), "green".parse().unwrap())]),
+            ..ColorOptions::default()
+        };
+        assert_eq!(
+            options.palette_color(None, "/notes/work.org").as_deref(),
+            Some("#000000")
+        );
+        assert_eq!(
+            options.palette_color(None, "/notes/home.org").as_deref(),
+            Some("#008000")
+        );
+        assert_eq!(
+            ColorOptions::default().palette_color(Some("work"), ""),
+            None
+        );
+    }
+}
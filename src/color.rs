@@ -0,0 +1,369 @@
+//! Colors: parsing of the values of color properties and options, and the
+//! contrast of a text on a background as defined by WCAG 2.
+//!
+//! A [`Color`] is read from
+//!
+//! - a CSS name: `steelblue`, `transparent`
+//! - hex: `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`
+//! - `rgb(70, 130, 180)`, `rgba(70 130 180 / 50%)`, `hsl(207, 44%, 49%)`
+//! - an Emacs name, in any case and with or without spaces: `SteelBlue3`,
+//!   `dark slate gray`, `gray50`. CSS names win over the few Emacs names
+//!   with another value (`green`, `gray`, `maroon`, `purple`).
+//!
+//! and always written as `#rrggbb`, or `#rrggbbaa` when it is not opaque.
+use crate::color_names::{CSS_COLORS, EMACS_COLORS};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Red, green and blue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+pub const BLACK: Rgb = Rgb { r: 0, g: 0, b: 0 };
+pub const WHITE: Rgb = Rgb {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+
+impl Rgb {
+    /// `#rgb` or `#rrggbb`
+    pub fn from_hex(value: &str) -> Option<Rgb> {
+        let hex = value.strip_prefix('#')?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize, len: usize| {
+            let value = u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).unwrap_or_default();
+            if len == 1 {
+                value * 17
+            } else {
+                value
+            }
+        };
+        match hex.len() {
+            3 => Some(Rgb {
+                r: channel(0, 1),
+                g: channel(1, 1),
+                b: channel(2, 1),
+            }),
+            6 => Some(Rgb {
+                r: channel(0, 2),
+                g: channel(1, 2),
+                b: channel(2, 2),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Relative luminance, from 0 (black) to 1 (white)
+    pub fn luminance(&self) -> f64 {
+        let linear = |channel: u8| {
+            let c = channel as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Contrast ratio with another color, from 1 to 21
+    pub fn contrast(&self, other: &Rgb) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Black or white, whichever is more readable on this background
+    pub fn text_color(&self) -> Rgb {
+        if self.contrast(&BLACK) >= self.contrast(&WHITE) {
+            BLACK
+        } else {
+            WHITE
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// A color and its opacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub rgb: Rgb,
+    /// 255 for an opaque color
+    pub alpha: u8,
+}
+
+impl Color {
+    fn from_u32(value: u32) -> Color {
+        Color {
+            rgb: Rgb {
+                r: (value >> 16) as u8,
+                g: (value >> 8) as u8,
+                b: value as u8,
+            },
+            alpha: 255,
+        }
+    }
+
+    /// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+    fn from_hex(hex: &str) -> Option<Color> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let len = match hex.len() {
+            3 | 4 => 1,
+            6 | 8 => 2,
+            _ => return None,
+        };
+        let channel = |i: usize| {
+            let value = u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).unwrap_or_default();
+            if len == 1 {
+                value * 17
+            } else {
+                value
+            }
+        };
+        Some(Color {
+            rgb: Rgb {
+                r: channel(0),
+                g: channel(1),
+                b: channel(2),
+            },
+            alpha: if hex.len() / len == 4 {
+                channel(3)
+            } else {
+                255
+            },
+        })
+    }
+
+    /// CSS name, then Emacs name
+    fn from_name(name: &str) -> Option<Color> {
+        let name: String = name
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+        if name == "transparent" {
+            return Some(Color {
+                rgb: BLACK,
+                alpha: 0,
+            });
+        }
+        [CSS_COLORS, EMACS_COLORS].iter().find_map(|colors| {
+            colors
+                .binary_search_by(|(known, _)| known.cmp(&name.as_str()))
+                .ok()
+                .map(|i| Color::from_u32(colors[i].1))
+        })
+    }
+
+    /// Arguments of `rgb()` or `hsl()`, separated by commas or by spaces
+    /// with the alpha after a `/`
+    fn from_function(name: &str, arguments: &str) -> Result<Color, String> {
+        let arguments: Vec<&str> = arguments
+            .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|argument| !argument.is_empty())
+            .collect();
+        let (channels, alpha) = match arguments.len() {
+            3 => (&arguments[..], None),
+            4 => (&arguments[..3], Some(arguments[3])),
+            _ => return Err(format!("{}() takes 3 or 4 values", name)),
+        };
+        let alpha = match alpha {
+            Some(alpha) => (parse_fraction(alpha, 1.0)?.clamp(0.0, 1.0) * 255.0).round() as u8,
+            None => 255,
+        };
+        let rgb = match name {
+            "rgb" | "rgba" => {
+                let channel = |value: &str| -> Result<u8, String> {
+                    Ok((parse_fraction(value, 255.0)?.clamp(0.0, 1.0) * 255.0).round() as u8)
+                };
+                Rgb {
+                    r: channel(channels[0])?,
+                    g: channel(channels[1])?,
+                    b: channel(channels[2])?,
+                }
+            }
+            "hsl" | "hsla" => {
+                let hue = channels[0].strip_suffix("deg").unwrap_or(channels[0]);
+                let hue: f64 = hue.parse().map_err(|_| format!("invalid hue {:?}", hue))?;
+                let percent = |value: &str| -> Result<f64, String> {
+                    let number = value.strip_suffix('%').unwrap_or(value);
+                    number
+                        .parse::<f64>()
+                        .map(|number| (number / 100.0).clamp(0.0, 1.0))
+                        .map_err(|_| format!("invalid percentage {:?}", value))
+                };
+                hsl_to_rgb(hue, percent(channels[1])?, percent(channels[2])?)
+            }
+            _ => return Err(format!("unknown color function {}()", name)),
+        };
+        Ok(Color { rgb, alpha })
+    }
+}
+
+/// A number from 0 to `max`, or a percentage, as a fraction of 1
+fn parse_fraction(value: &str, max: f64) -> Result<f64, String> {
+    let (number, max) = match value.strip_suffix('%') {
+        Some(number) => (number, 100.0),
+        None => (value, max),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .map(|number| number / max)
+        .ok_or_else(|| format!("invalid number {:?}", value))
+}
+
+/// Saturation and lightness are fractions of 1
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> Rgb {
+    let channel = |n: f64| {
+        let k = (n + hue / 30.0).rem_euclid(12.0);
+        let a = saturation * lightness.min(1.0 - lightness);
+        let value = lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0);
+        (value * 255.0).round() as u8
+    };
+    Rgb {
+        r: channel(0.0),
+        g: channel(8.0),
+        b: channel(4.0),
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Color, String> {
+        let value = value.trim();
+        if let Some(hex) = value.strip_prefix('#') {
+            return Color::from_hex(hex).ok_or_else(|| format!("invalid hex color {:?}", value));
+        }
+        if let Some((name, arguments)) = value.split_once('(') {
+            let arguments = arguments
+                .strip_suffix(')')
+                .ok_or_else(|| format!("missing ) in {:?}", value))?;
+            return Color::from_function(&name.trim().to_lowercase(), arguments);
+        }
+        Color::from_name(value).ok_or_else(|| format!("unknown color {:?}", value))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.rgb)?;
+        if self.alpha != 255 {
+            write!(f, "{:02x}", self.alpha)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        color.to_string()
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Color, String> {
+        value.parse()
+    }
+}
+
+/// A color value in its normalized form
+pub fn normalize_color(value: &str) -> Result<String, String> {
+    value.parse::<Color>().map(|color| color.to_string())
+}
+
+/// Color of a text readable on the background, None when the background
+/// is not a color
+pub fn contrast_text_color(background: &str) -> Option<String> {
+    background
+        .parse::<Color>()
+        .ok()
+        .map(|background| background.rgb.text_color().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_colors() {
+        assert_eq!(
+            Rgb::from_hex("#f80"),
+            Some(Rgb {
+                r: 255,
+                g: 136,
+                b: 0
+            })
+        );
+        assert_eq!(Rgb::from_hex("#336699").unwrap().to_string(), "#336699");
+        assert_eq!(Rgb::from_hex("#12345"), None);
+        assert_eq!(Rgb::from_hex("336699"), None);
+    }
+
+    #[test]
+    fn contrast_of_text() {
+        assert!((BLACK.contrast(&WHITE) - 21.0).abs() < 1e-9);
+        assert_eq!(contrast_text_color("#ffff00").as_deref(), Some("#000000"));
+        assert_eq!(contrast_text_color("#336699").as_deref(), Some("#ffffff"));
+        assert_eq!(contrast_text_color("navy").as_deref(), Some("#ffffff"));
+        assert_eq!(contrast_text_color("gren"), None);
+    }
+
+    #[test]
+    fn parse_colors() {
+        let normalized = |value: &str| normalize_color(value).ok();
+        assert_eq!(normalized("SteelBlue").as_deref(), Some("#4682b4"));
+        assert_eq!(normalized("#ABC").as_deref(), Some("#aabbcc"));
+        assert_eq!(normalized("#11223380").as_deref(), Some("#11223380"));
+        assert_eq!(normalized("#f008").as_deref(), Some("#ff000088"));
+        assert_eq!(normalized("transparent").as_deref(), Some("#00000000"));
+        assert_eq!(normalized("rgb(70, 130, 180)").as_deref(), Some("#4682b4"));
+        assert_eq!(
+            normalized("rgba(100% 0% 0% / 50%)").as_deref(),
+            Some("#ff000080")
+        );
+        assert_eq!(
+            normalized("hsl(120, 100%, 25%)").as_deref(),
+            Some("#008000")
+        );
+        assert_eq!(normalized("hsl(0deg 0% 100%)").as_deref(), Some("#ffffff"));
+        // Emacs names
+        assert_eq!(normalized("SteelBlue3").as_deref(), Some("#4f94cd"));
+        assert_eq!(normalized("dark slate gray").as_deref(), Some("#2f4f4f"));
+        assert_eq!(normalized("gray50").as_deref(), Some("#7f7f7f"));
+        // CSS wins
+        assert_eq!(normalized("green").as_deref(), Some("#008000"));
+
+        assert_eq!(
+            "gren".parse::<Color>(),
+            Err("unknown color \"gren\"".to_string())
+        );
+        assert_eq!(
+            "#12345".parse::<Color>(),
+            Err("invalid hex color \"#12345\"".to_string())
+        );
+        assert!("#ggg".parse::<Color>().is_err());
+        assert!("rgb(1, 2)".parse::<Color>().is_err());
+        assert!("rgb(1, 2, 3".parse::<Color>().is_err());
+        assert!("cmyk(0, 0, 0, 0)".parse::<Color>().is_err());
+    }
+}
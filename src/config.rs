@@ -0,0 +1,424 @@
+//! Configuration file, in JSON5, of a whole conversion: the Org files to
+//! read, the [`ConvertOptions`], the output, written by
+//! [`crate::parse::config_to_fc_json`], and the server.
+//!
+//! ```json5
+//! {
+//!   // Org files: paths, directories walked down and patterns
+//!   sources: {
+//!     files: ["~/org/inbox.org"],
+//!     dirs: ["projects"],
+//!     globs: ["archive/2022-*.org"],
+//!     walk: { exclude: ["drafts"], max_depth: 2 },
+//!     // As `org-agenda-files`: a list, or a file listing them
+//!     agenda_files: "~/.emacs.d/agenda-files",
+//!   },
+//!   // The conversion options, e.g.
+//!   ignore_before_days: 30,
+//!   prefixes: { locale: "ja" },
+//!   todo_colors: { WAIT: "orange" },
+//!   output: { path: "public/events.json", clocks: "include" },
+//!   scan: { threads: 4, cache: ".cache/events.json" },
+//!   server: { port: 8080 },
+//! }
+//! ```
+//!
+//! Relative paths are relative to the directory of the configuration file,
+//! and `~/` is the home directory. Unknown keys at the top level are errors,
+//! so that a misspelled option is not silently ignored.
+use crate::agenda::{expand_path, AgendaFiles};
+use crate::fullcalendar::FCEvent;
+use crate::options::ConvertOptions;
+use crate::scan::ScanOptions;
+use crate::walk::{org_files, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Org files to convert
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sources {
+    pub files: Vec<String>,
+    /// Directories walked down for Org files
+    pub dirs: Vec<String>,
+    /// Glob patterns, e.g. `notes/**/*.org`
+    pub globs: Vec<String>,
+    /// How the directories are walked
+    pub walk: WalkOptions,
+    /// Files and directories of the agenda, see [`crate::agenda`]
+    pub agenda_files: Option<AgendaFiles>,
+}
+
+impl Sources {
+    /// The files of the sources without duplicates: the files and the
+    /// agenda files in the order of their lists, the files of the
+    /// directories and of the patterns sorted
+    pub fn paths(&self) -> Result<Vec<PathBuf>, String> {
+        let mut paths: Vec<PathBuf> = self.files.iter().map(PathBuf::from).collect();
+        let mut walked = org_files(&self.dirs, &self.walk)?;
+        walked.sort();
+        paths.extend(walked);
+        if let Some(agenda_files) = &self.agenda_files {
+            paths.extend(agenda_files.paths(Path::new(""))?);
+        }
+        let mut globbed: Vec<PathBuf> = Vec::new();
+        for pattern in &self.globs {
+            let entries = glob::glob(pattern)
+                .map_err(|error| format!("invalid pattern \"{}\": {}", pattern, error))?;
+            for entry in entries {
+                globbed.push(entry.map_err(|error| error.to_string())?);
+            }
+        }
+        globbed.sort();
+        paths.extend(globbed);
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        paths.retain(|path| seen.insert(path.clone()));
+        Ok(paths)
+    }
+
+    /// Make the paths relative to a directory, and expand `~/`
+    fn resolve(&mut self, base: &Path) {
+        for path in self
+            .files
+            .iter_mut()
+            .chain(&mut self.dirs)
+            .chain(&mut self.globs)
+        {
+            *path = resolve_path(path, base);
+        }
+        match &mut self.agenda_files {
+            Some(AgendaFiles::List(entries)) => {
+                for entry in entries {
+                    *entry = resolve_path(entry, base);
+                }
+            }
+            Some(AgendaFiles::File(file)) => *file = resolve_path(file, base),
+            None => {}
+        }
+    }
+}
+
+/// Which events are output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockEvents {
+    /// Only the events of the planning lines
+    #[default]
+    Exclude,
+    /// Also those of the CLOCK lines
+    Include,
+    /// Only those of the CLOCK lines
+    Only,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Indented JSON
+    #[default]
+    Pretty,
+    /// JSON on one line
+    Compact,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// File the events are written to, the standard output when None
+    pub path: Option<String>,
+    pub format: OutputFormat,
+    pub clocks: ClockEvents,
+}
+
+impl OutputConfig {
+    /// The events as JSON
+    pub fn render(&self, events: &[FCEvent]) -> String {
+        match self.format {
+            OutputFormat::Pretty => serde_json::to_string_pretty(events),
+            OutputFormat::Compact => serde_json::to_string(events),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Write the events to the file, making its directory, or else to
+    /// `out`, e.g. the standard output
+    pub fn write(&self, events: &[FCEvent], out: &mut impl Write) -> Result<(), String> {
+        let json = self.render(events);
+        let path = match &self.path {
+            Some(path) => Path::new(path),
+            None => return writeln!(out, "{}", json).map_err(|error| error.to_string()),
+        };
+        let located = |error: std::io::Error| format!("{}: {}", path.display(), error);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(located)?;
+        }
+        fs::write(path, json).map_err(located)
+    }
+}
+
+/// Where the events are served, for the programs which serve them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Path of the events, e.g. `/events.json`
+    pub path: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            path: "/events.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub sources: Sources,
+    #[serde(flatten)]
+    pub options: ConvertOptions,
+    pub output: OutputConfig,
+    /// Threads and cache of the conversion of the files
+    pub scan: ScanOptions,
+    pub server: Option<ServerConfig>,
+}
+
+impl Config {
+    /// Read a configuration file; its relative paths are made relative to
+    /// its directory
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, String> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut config =
+            Config::from_json5(&text).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        config.sources.resolve(base);
+        for path in [&mut config.output.path, &mut config.scan.cache]
+            .into_iter()
+            .flatten()
+        {
+            *path = resolve_path(path, base);
+        }
+        Ok(config)
+    }
+
+    /// Read a configuration from JSON5 text and validate it
+    pub fn from_json5(text: &str) -> Result<Config, String> {
+        let value: serde_json::Value = json5::from_str(text).map_err(location_message)?;
+        let options = match value {
+            serde_json::Value::Object(options) => options,
+            _ => return Err("the configuration is not an object".to_string()),
+        };
+        let known = serde_json::to_value(Config::default()).unwrap_or_default();
+        for (key, value) in &options {
+            if known.get(key).is_none() {
+                return Err(format!("unknown option \"{}\"", key));
+            }
+            // One option at a time, to tell which one is wrong
+            let option = serde_json::Map::from_iter([(key.clone(), value.clone())]);
+            serde_json::from_value::<Config>(serde_json::Value::Object(option))
+                .map_err(|error| format!("{}: {}", key, error))?;
+        }
+        let config: Config = serde_json::from_value(serde_json::Value::Object(options))
+            .map_err(|error| error.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.options.ignore_before_days < 0 || self.options.ignore_after_days < 0 {
+            return Err(
+                "ignore_before_days and ignore_after_days must not be negative".to_string(),
+            );
+        }
+        if let Some(expand) = &self.options.expand_recurring {
+            if expand.days_before < 0 || expand.days_after < 0 {
+                return Err(
+                    "expand_recurring.days_before and days_after must not be negative".to_string(),
+                );
+            }
+        }
+        if self.scan.threads == Some(0) {
+            return Err("scan.threads must not be 0".to_string());
+        }
+        if let Some(server) = &self.server {
+            if server.port == 0 {
+                return Err("server.port must not be 0".to_string());
+            }
+            if !server.path.starts_with('/') {
+                return Err(format!(
+                    "server.path \"{}\" must start with \"/\"",
+                    server.path
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The message of a JSON5 syntax error, after its line and column
+fn location_message(error: json5::Error) -> String {
+    let json5::Error::Message { msg, location } = error;
+    match location {
+        Some(location) => format!(
+            "line {}, column {}: {}",
+            location.line, location.column, msg
+        ),
+        None => msg,
+    }
+}
+
+/// A path relative to a directory, with `~/` expanded
+fn resolve_path(path: &str, base: &Path) -> String {
+    expand_path(path, base).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fullcalendar::FCStart;
+    use crate::options::DoneItems;
+    use crate::testing::TempDir;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn read_json5() {
+        let config = Config::from_json5(
+            r#"{
+  // Comments and trailing commas
+  sources: { dirs: ['notes'], },
+  ignore_before_days: 30,
+  done_items: 'grey',
+  todo_colors: { WAIT: 'orange' },
+  output: { format: 'compact', clocks: 'include' },
+  server: { port: 3000 },
+}"#,
+        )
+        .unwrap();
+        assert_eq!(config.sources.dirs, ["notes"]);
+        assert_eq!(config.options.ignore_before_days, 30);
+        assert_eq!(config.options.done_items, DoneItems::Grey);
+        assert_eq!(config.options.todo_colors["WAIT"].to_string(), "#ffa500");
+        assert_eq!(config.output.clocks, ClockEvents::Include);
+        let server = config.server.unwrap();
+        assert_eq!((server.host.as_str(), server.port), ("127.0.0.1", 3000));
+    }
+
+    #[test]
+    fn report_errors() {
+        assert_eq!(
+            Config::from_json5("{ done_item: 'grey' }").unwrap_err(),
+            "unknown option \"done_item\""
+        );
+        assert_eq!(
+            Config::from_json5("{ done_items: 'gray' }").unwrap_err(),
+            "done_items: unknown variant `gray`, expected one of `show`, `grey`, `hide`"
+        );
+        assert_eq!(
+            Config::from_json5("{ output: { clock: 'only' } }").unwrap_err(),
+            "output: unknown field `clock`, expected one of `path`, `format`, `clocks`"
+        );
+        assert!(Config::from_json5("{ scan: { threads: 0 } }")
+            .unwrap_err()
+            .contains("threads"));
+        assert!(Config::from_json5("{ server: { port: 0 } }")
+            .unwrap_err()
+            .contains("port"));
+        assert!(Config::from_json5("{ server: { path: 'events.json' } }")
+            .unwrap_err()
+            .contains("server.path"));
+        assert!(Config::from_json5("{\n  sources: ")
+            .unwrap_err()
+            .starts_with("line 2, column 12: "));
+    }
+
+    #[test]
+    fn write_output_file() {
+        let dir = TempDir::new("output", &[]);
+        let output = OutputConfig {
+            path: Some(dir.path("public/events.json")),
+            format: OutputFormat::Compact,
+            ..OutputConfig::default()
+        };
+        let event = FCEvent::builder(
+            "Review",
+            FCStart::Day(NaiveDate::from_ymd_opt(2022, 8, 1).unwrap()),
+        )
+        .build();
+        let json = r#"[{"title":"Review","start":"2022-08-01"}]"#;
+        let mut out: Vec<u8> = Vec::new();
+        output.write(&[event.clone()], &mut out).unwrap();
+        assert!(out.is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.join("public/events.json")).unwrap(),
+            json
+        );
+
+        let output = OutputConfig {
+            path: None,
+            ..output
+        };
+        output.write(&[event], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", json));
+    }
+
+    #[test]
+    fn keep_the_order_of_the_lists() {
+        let dir = TempDir::new(
+            "sources",
+            &[
+                ("b.org", ""),
+                ("a.org", ""),
+                ("notes/d.org", ""),
+                ("notes/c.org", ""),
+            ],
+        );
+        let sources = Sources {
+            files: vec![dir.path("b.org")],
+            globs: vec![dir.path("notes/*.org")],
+            agenda_files: Some(AgendaFiles::List(vec![
+                dir.path("b.org"),
+                dir.path("a.org"),
+                dir.path("notes/d.org"),
+            ])),
+            ..Sources::default()
+        };
+        assert_eq!(
+            sources.paths().unwrap(),
+            [
+                dir.join("b.org"),
+                dir.join("a.org"),
+                dir.join("notes/d.org"),
+                dir.join("notes/c.org")
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_relative_paths() {
+        let mut sources = Sources {
+            files: vec!["/org/inbox.org".to_string()],
+            dirs: vec!["projects".to_string()],
+            agenda_files: Some(AgendaFiles::File("agenda-files".to_string())),
+            ..Sources::default()
+        };
+        sources.resolve(Path::new("/home/me/config"));
+        assert_eq!(sources.files, ["/org/inbox.org"]);
+        assert_eq!(sources.dirs, ["/home/me/config/projects"]);
+        assert_eq!(
+            sources.agenda_files,
+            Some(AgendaFiles::File(
+                "/home/me/config/agenda-files".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/// A problem in the Org data which does not stop the conversion,
+/// e.g. a property with a value that cannot be used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// File the headline is in ("" for text given directly)
+    pub file_path: String,
+    /// Title of the headline
+    pub headline: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file_path: &str, headline: &str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            file_path: file_path.to_string(),
+            headline: headline.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file_path.is_empty() {
+            write!(f, "\"{}\": {}", self.headline, self.message)
+        } else {
+            write!(
+                f,
+                "{}: \"{}\": {}",
+                self.file_path, self.headline, self.message
+            )
+        }
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// Print diagnostics to stderr
+pub fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("warning: {}", diagnostic);
+    }
+}
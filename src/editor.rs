@@ -0,0 +1,198 @@
+//! Links from the events back to their headlines, so that clicking an event
+//! in the browser opens the headline in the editor.
+//!
+//! A link is made from the scheme of the options:
+//!
+//! | Scheme         | Link                                                  |
+//! |----------------|-------------------------------------------------------|
+//! | `org-protocol` | `org-protocol://open-heading?file=/notes.org&line=12` |
+//! | `vscode`       | `vscode://file/notes.org:12`                          |
+//! | `id`           | `id:1234-abcd`                                        |
+//!
+//! Org's own `org-protocol` handlers cannot open a file at a line
+//! (`open-source` maps published URLs to files, and takes no line), so
+//! `open-heading` is a handler of its own, to be registered in Emacs:
+//!
+//! ```elisp
+//! (require 'org-protocol)
+//! (defun org-protocol-open-heading (info)
+//!   "Open the file of INFO at its line."
+//!   (find-file (plist-get info :file))
+//!   (goto-char (point-min))
+//!   (forward-line (1- (string-to-number (plist-get info :line))))
+//!   (org-fold-show-context)
+//!   nil)
+//! (add-to-list 'org-protocol-protocol-alist
+//!              '("open-heading" :protocol "open-heading"
+//!                :function org-protocol-open-heading))
+//! ```
+//!
+//! with `org-protocol://` links handed to `emacsclient` by the desktop, as
+//! for Org's handlers.
+//!
+//! or from a template, e.g. `idea://open?file={path}&line={line}`, with the
+//! fields `path` (absolute path of the file), `file_url` (its `file://` URL),
+//! `line`, `offset` and `id` (the `ID` property), percent-encoded. An event
+//! gets no link when a field has no value, e.g. `id` for a headline without
+//! an ID, or `line` for an `Org` parsed without its text.
+//!
+//! The link is `extendedProps.editorUrl`, and the `url` unless `FC_URL` or
+//! the title's link set one.
+use crate::outline::Position;
+use crate::template::Template;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Characters kept as they are in the fields, besides letters and digits
+const UNRESERVED: &str = "-._~";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkScheme {
+    /// The `open-heading` handler above
+    OrgProtocol,
+    Vscode,
+    Id,
+}
+
+impl LinkScheme {
+    fn template(&self) -> &'static str {
+        match self {
+            LinkScheme::OrgProtocol => "org-protocol://open-heading?file={path}&line={line}",
+            LinkScheme::Vscode => "vscode://file{path}:{line}",
+            LinkScheme::Id => "id:{id}",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorLinkOptions {
+    /// No links when None
+    pub scheme: Option<LinkScheme>,
+    /// Template of the links instead of the scheme's
+    pub template: Option<Template>,
+    /// Replace the `url` set by `FC_URL` or the title's link
+    pub replace_url: bool,
+}
+
+impl EditorLinkOptions {
+    /// Link to the headline at a position of a file, None when no link is
+    /// set or a field of it has no value
+    pub fn link(
+        &self,
+        file_path: &str,
+        position: Option<Position>,
+        id: Option<&str>,
+    ) -> Option<String> {
+        let template = match (&self.template, self.scheme) {
+            (Some(template), _) => template.clone(),
+            (None, Some(scheme)) => Template::parse(scheme.template()),
+            (None, None) => return None,
+        };
+        let path = absolute_path(file_path);
+        let mut complete = true;
+        let link = template.render(|field| {
+            let value = match field {
+                "path" => path.as_deref().map(|path| encode(path, "/:")),
+                "file_url" => path
+                    .as_deref()
+                    .map(|path| encode(&format!("file://{}", path), "")),
+                "line" => position.map(|position| position.line.to_string()),
+                "offset" => position.map(|position| position.offset.to_string()),
+                "id" => id.map(|id| encode(id, "")),
+                _ => None,
+            };
+            complete &= value.is_some();
+            value
+        });
+        complete.then_some(link)
+    }
+}
+
+/// The absolute path of a file with `/` separators, starting with `/` also
+/// on Windows
+fn absolute_path(file_path: &str) -> Option<String> {
+    if file_path.is_empty() {
+        return None;
+    }
+    let path = Path::new(file_path);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().ok()?.join(path)
+    };
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        Some(path)
+    } else {
+        Some(format!("/{}", path))
+    }
+}
+
+/// Percent-encode a text, keeping the unreserved characters and `keep`
+fn encode(text: &str, keep: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        let c = char::from(byte);
+        if c.is_ascii_alphanumeric() || UNRESERVED.contains(c) || keep.contains(c) {
+            encoded.push(c);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION: Option<Position> = Some(Position {
+        line: 12,
+        offset: 340,
+    });
+
+    fn link(scheme: LinkScheme, position: Option<Position>, id: Option<&str>) -> Option<String> {
+        let options = EditorLinkOptions {
+            scheme: Some(scheme),
+            ..EditorLinkOptions::default()
+        };
+        options.link("/home/me/my notes.org", position, id)
+    }
+
+    #[test]
+    fn links_of_schemes() {
+        assert_eq!(
+            link(LinkScheme::OrgProtocol, POSITION, None).as_deref(),
+            Some("org-protocol://open-heading?file=/home/me/my%20notes.org&line=12")
+        );
+        assert_eq!(
+            link(LinkScheme::Vscode, POSITION, None).as_deref(),
+            Some("vscode://file/home/me/my%20notes.org:12")
+        );
+        assert_eq!(
+            link(LinkScheme::Id, None, Some("1234 abcd")).as_deref(),
+            Some("id:1234%20abcd")
+        );
+        assert_eq!(link(LinkScheme::Id, POSITION, None), None);
+        assert_eq!(link(LinkScheme::OrgProtocol, None, Some("1234")), None);
+        assert_eq!(
+            EditorLinkOptions::default().link("a.org", POSITION, None),
+            None
+        );
+    }
+
+    #[test]
+    fn links_of_templates() {
+        let options: EditorLinkOptions = serde_json::from_str(
+            r#"{"template": "idea://open?file={path}&line={line}&at={offset}"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            options.link("/notes.org", POSITION, None).as_deref(),
+            Some("idea://open?file=/notes.org&line=12&at=340")
+        );
+        assert_eq!(options.link("", POSITION, None), None);
+    }
+}
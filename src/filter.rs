@@ -0,0 +1,111 @@
+//! Which headlines become events.
+//!
+//! A headline is kept when it passes every include list which is not empty
+//! and matches none of the exclude lists. Tags include the inherited ones
+//! and `#+FILETAGS`, so excluding a tag excludes the whole subtree.
+//!
+//! Like Org's export, subtrees tagged with `#+EXCLUDE_TAGS` (`noexport`
+//! without the keyword) or `ARCHIVE` are left out unless asked otherwise.
+use crate::fullcalendar::make_property;
+use crate::outline::HeadlineContext;
+use crate::settings::{FileSettings, ARCHIVE_TAG};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    /// Keep only headlines with one of these tags
+    pub include_tags: Vec<String>,
+    /// Leave out headlines with one of these tags
+    pub exclude_tags: Vec<String>,
+    /// Keep only headlines with one of these TODO keywords
+    pub include_keywords: Vec<String>,
+    /// Leave out headlines with one of these TODO keywords
+    pub exclude_keywords: Vec<String>,
+    /// Keep only headlines with one of these priorities, e.g. `A`
+    pub include_priorities: Vec<char>,
+    /// Leave out headlines with one of these priorities
+    pub exclude_priorities: Vec<char>,
+    /// Keep only headlines matching one of these properties
+    pub include_properties: Vec<PropertyFilter>,
+    /// Leave out headlines matching one of these properties
+    pub exclude_properties: Vec<PropertyFilter>,
+    /// Lowest headline level to keep (the text before the first headline is level 0)
+    pub min_level: Option<usize>,
+    /// Deepest headline level to keep
+    pub max_level: Option<usize>,
+    /// Keep subtrees tagged with `#+EXCLUDE_TAGS` or `noexport`
+    pub keep_excluded: bool,
+    /// Keep subtrees tagged `ARCHIVE`
+    pub keep_archived: bool,
+}
+
+/// A property which is set, or set to a given value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyFilter {
+    pub key: String,
+    /// Any value when None
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+impl PropertyFilter {
+    pub fn new(key: &str, value: Option<&str>) -> PropertyFilter {
+        PropertyFilter {
+            key: key.to_string(),
+            value: value.map(|value| value.to_string()),
+        }
+    }
+
+    fn matches(&self, headline: &HeadlineContext) -> bool {
+        match (make_property(&headline.title, &self.key), &self.value) {
+            (Some(value), Some(expected)) => value == *expected,
+            (Some(..), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl Filter {
+    /// Does the headline become events?
+    pub fn accepts(&self, headline: &HeadlineContext, settings: &FileSettings) -> bool {
+        let has_tag = |tags: &[&str]| headline.tags.iter().any(|tag| tags.contains(&tag.as_str()));
+        if !self.keep_excluded && has_tag(&settings.exclude_tags()) {
+            return false;
+        }
+        if !self.keep_archived && has_tag(&[ARCHIVE_TAG]) {
+            return false;
+        }
+
+        let tags = |list: &[String]| headline.tags.iter().any(|tag| list.contains(tag));
+        let keyword = |list: &[String]| {
+            headline
+                .title
+                .keyword
+                .as_ref()
+                .is_some_and(|keyword| list.iter().any(|item| item == keyword))
+        };
+        let priority = |list: &[char]| {
+            headline
+                .title
+                .priority
+                .is_some_and(|priority| list.contains(&priority))
+        };
+        let property = |list: &[PropertyFilter]| list.iter().any(|filter| filter.matches(headline));
+        fn included<T>(list: &[T], matches: bool) -> bool {
+            list.is_empty() || matches
+        }
+
+        let level = headline.title.level;
+        included(&self.include_tags, tags(&self.include_tags))
+            && !tags(&self.exclude_tags)
+            && included(&self.include_keywords, keyword(&self.include_keywords))
+            && !keyword(&self.exclude_keywords)
+            && included(&self.include_priorities, priority(&self.include_priorities))
+            && !priority(&self.exclude_priorities)
+            && included(&self.include_properties, property(&self.include_properties))
+            && !property(&self.exclude_properties)
+            && self.min_level.is_none_or(|min| level >= min)
+            && self.max_level.is_none_or(|max| level <= max)
+    }
+}
@@ -1,17 +1,44 @@
+use crate::color::normalize_color;
+use crate::hash::stable_hash_all;
+use crate::prefix::{default_prefix, TimestampKind};
 use crate::timestamp::datetime_duration;
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use orgize::elements;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
 
 /// Struct for Fullcalendar events.
-#[derive(Serialize, Debug)]
+///
+/// Fields follow FullCalendar's event object. `description` and `filePath`
+/// are not part of it; FullCalendar moves them into `extendedProps` itself.
+///
+/// Events are ordered by start, end, title and id, and then by the rest of
+/// their contents, so that sorted vectors can be diffed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")] // https://serde.rs/attr-rename.html
 pub struct FCEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Unique identifier of the event
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Events sharing a group id are dragged and resized together
+    pub group_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Whether the event is shown in the all-day section
+    pub all_day: Option<bool>,
     /// The calendar's title. (Required)
     pub title: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     /// FC RRule
-    rrule: Option<FCRRule>,
+    pub rrule: Option<FCRRule>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    /// Occurrences of the RRule to skip. FullCalendar's rrule plugin reads
+    /// them next to `rrule`, not inside it.
+    pub exdate: Vec<FCStart>,
     /// The calendar's start date (with/without time).
     pub start: FCStart,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -20,6 +47,35 @@ pub struct FCEvent {
     #[serde(skip_serializing_if = "Option::is_none")]
     /// Duration (for RRULE)
     pub duration: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    /// Simple recurrence: days of week (0 = Sunday)
+    pub days_of_week: Vec<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Simple recurrence: start time of each occurrence
+    pub start_time: Option<NaiveTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Simple recurrence: end time of each occurrence
+    pub end_time: Option<NaiveTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Simple recurrence: first day of the series
+    pub start_recur: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Simple recurrence: day after the last day of the series
+    pub end_recur: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Link opened when the event is clicked
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    /// CSS classes attached to the event element
+    pub class_names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Whether the event can be dragged and resized
+    pub editable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Rendering type: auto, block, list-item, background, inverse-background or none
+    pub display: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     /// Description (for tooltips)
     pub description: Option<String>,
@@ -27,14 +83,37 @@ pub struct FCEvent {
     /// Event color
     pub color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    /// Background color
+    pub background_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Border color
+    pub border_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     /// Text color
     pub text_color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    /// Group id or "businessHours" the event may be dropped into
+    pub constraint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Whether other events may overlap this one
+    pub overlap: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    /// Resources the event belongs to (resource views)
+    pub resource_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    #[serde(default)]
+    /// Everything that is not a FullCalendar event property
+    pub extended_props: Map<String, Value>,
 }
 
 impl FCEvent {
     /// Constructor
+    ///
+    /// Prefer [`FCEvent::builder`], which names every field.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         title: String,
         start: FCStart,
@@ -46,21 +125,249 @@ impl FCEvent {
         text_color: Option<String>,
         file_path: Option<String>,
     ) -> FCEvent {
-        FCEvent {
-            title,
-            start,
-            rrule,
-            end,
-            duration,
-            description,
-            color,
-            text_color,
-            file_path,
+        FCEvent::builder(title, start)
+            .end(end)
+            .duration(duration)
+            .description(description)
+            .rrule(rrule)
+            .color(color)
+            .text_color(text_color)
+            .file_path(file_path)
+            .build()
+    }
+
+    /// Start building an event from its required fields
+    pub fn builder(title: impl Into<String>, start: FCStart) -> FCEventBuilder {
+        FCEventBuilder::new(title, start)
+    }
+}
+
+impl Ord for FCEvent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.start
+            .cmp(&other.start)
+            .then_with(|| self.end.cmp(&other.end))
+            .then_with(|| self.title.cmp(&other.title))
+            .then_with(|| self.id.cmp(&other.id))
+            .then_with(|| {
+                // Everything else: compare the JSON forms, which are equal
+                // exactly when the events are equal.
+                let lhs = serde_json::to_string(self).unwrap_or_default();
+                let rhs = serde_json::to_string(other).unwrap_or_default();
+                lhs.cmp(&rhs)
+            })
+    }
+}
+
+impl PartialOrd for FCEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Builder for [`FCEvent`] with a setter per field.
+///
+/// ```ignore
+/// let event = FCEvent::builder("Review", FCStart::Day(date))
+///     .url(Some("https://example.com".to_string()))
+///     .class_names(vec!["work".to_string()])
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct FCEventBuilder {
+    event: FCEvent,
+}
+
+impl FCEventBuilder {
+    /// Builder with a title and a start, and every other field empty
+    pub fn new(title: impl Into<String>, start: FCStart) -> FCEventBuilder {
+        FCEventBuilder {
+            event: FCEvent {
+                id: None,
+                group_id: None,
+                all_day: None,
+                title: title.into(),
+                rrule: None,
+                exdate: Vec::new(),
+                start,
+                end: None,
+                duration: None,
+                days_of_week: Vec::new(),
+                start_time: None,
+                end_time: None,
+                start_recur: None,
+                end_recur: None,
+                url: None,
+                class_names: Vec::new(),
+                editable: None,
+                display: None,
+                description: None,
+                color: None,
+                background_color: None,
+                border_color: None,
+                text_color: None,
+                constraint: None,
+                overlap: None,
+                resource_ids: Vec::new(),
+                file_path: None,
+                extended_props: Map::new(),
+            },
         }
     }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.event.title = title.into();
+        self
+    }
+
+    pub fn start(mut self, start: FCStart) -> Self {
+        self.event.start = start;
+        self
+    }
+
+    pub fn id(mut self, id: Option<String>) -> Self {
+        self.event.id = id;
+        self
+    }
+
+    pub fn group_id(mut self, group_id: Option<String>) -> Self {
+        self.event.group_id = group_id;
+        self
+    }
+
+    pub fn all_day(mut self, all_day: Option<bool>) -> Self {
+        self.event.all_day = all_day;
+        self
+    }
+
+    pub fn rrule(mut self, rrule: Option<FCRRule>) -> Self {
+        self.event.rrule = rrule;
+        self
+    }
+
+    pub fn exdate(mut self, exdate: Vec<FCStart>) -> Self {
+        self.event.exdate = exdate;
+        self
+    }
+
+    pub fn end(mut self, end: Option<NaiveDateTime>) -> Self {
+        self.event.end = end;
+        self
+    }
+
+    pub fn duration(mut self, duration: Option<String>) -> Self {
+        self.event.duration = duration;
+        self
+    }
+
+    pub fn days_of_week(mut self, days_of_week: Vec<u32>) -> Self {
+        self.event.days_of_week = days_of_week;
+        self
+    }
+
+    pub fn start_time(mut self, start_time: Option<NaiveTime>) -> Self {
+        self.event.start_time = start_time;
+        self
+    }
+
+    pub fn end_time(mut self, end_time: Option<NaiveTime>) -> Self {
+        self.event.end_time = end_time;
+        self
+    }
+
+    pub fn start_recur(mut self, start_recur: Option<NaiveDate>) -> Self {
+        self.event.start_recur = start_recur;
+        self
+    }
+
+    pub fn end_recur(mut self, end_recur: Option<NaiveDate>) -> Self {
+        self.event.end_recur = end_recur;
+        self
+    }
+
+    pub fn url(mut self, url: Option<String>) -> Self {
+        self.event.url = url;
+        self
+    }
+
+    pub fn class_names(mut self, class_names: Vec<String>) -> Self {
+        self.event.class_names = class_names;
+        self
+    }
+
+    pub fn editable(mut self, editable: Option<bool>) -> Self {
+        self.event.editable = editable;
+        self
+    }
+
+    pub fn display(mut self, display: Option<String>) -> Self {
+        self.event.display = display;
+        self
+    }
+
+    pub fn description(mut self, description: Option<String>) -> Self {
+        self.event.description = description;
+        self
+    }
+
+    pub fn color(mut self, color: Option<String>) -> Self {
+        self.event.color = color;
+        self
+    }
+
+    pub fn background_color(mut self, background_color: Option<String>) -> Self {
+        self.event.background_color = background_color;
+        self
+    }
+
+    pub fn border_color(mut self, border_color: Option<String>) -> Self {
+        self.event.border_color = border_color;
+        self
+    }
+
+    pub fn text_color(mut self, text_color: Option<String>) -> Self {
+        self.event.text_color = text_color;
+        self
+    }
+
+    pub fn constraint(mut self, constraint: Option<String>) -> Self {
+        self.event.constraint = constraint;
+        self
+    }
+
+    pub fn overlap(mut self, overlap: Option<bool>) -> Self {
+        self.event.overlap = overlap;
+        self
+    }
+
+    pub fn resource_ids(mut self, resource_ids: Vec<String>) -> Self {
+        self.event.resource_ids = resource_ids;
+        self
+    }
+
+    pub fn file_path(mut self, file_path: Option<String>) -> Self {
+        self.event.file_path = file_path;
+        self
+    }
+
+    pub fn extended_props(mut self, extended_props: Map<String, Value>) -> Self {
+        self.event.extended_props = extended_props;
+        self
+    }
+
+    /// Set a single `extendedProps` entry
+    pub fn extended_prop(mut self, key: impl Into<String>, value: Value) -> Self {
+        self.event.extended_props.insert(key.into(), value);
+        self
+    }
+
+    pub fn build(self) -> FCEvent {
+        self.event
+    }
 }
+
 /// RRule for Fullcalendar events.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FCRRule {
     pub dtstart: FCStart,
     /// Time unit: yearly, weekly, daily, hourly
@@ -69,10 +376,49 @@ pub struct FCRRule {
     pub freq: String,
     /// Repeater interval
     pub interval: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Weekdays: "mo", "tu", "we", "th", "fr", "sa", "su"
+    pub byweekday: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Days of the month (negative from the end)
+    pub bymonthday: Vec<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Months (1 = January)
+    pub bymonth: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Positions within the set of each interval (negative from the end)
+    pub bysetpos: Vec<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Number of occurrences
+    pub count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Last possible occurrence
+    pub until: Option<FCStart>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// First day of the week for weekly rules
+    pub wkst: Option<String>,
+}
+
+impl FCRRule {
+    /// RRule with a frequency and an interval only
+    pub fn new(dtstart: FCStart, freq: &str, interval: usize) -> FCRRule {
+        FCRRule {
+            dtstart,
+            freq: freq.to_string(),
+            interval,
+            byweekday: Vec::new(),
+            bymonthday: Vec::new(),
+            bymonth: Vec::new(),
+            bysetpos: Vec::new(),
+            count: None,
+            until: None,
+            wkst: None,
+        }
+    }
 }
 
 /// Date format for FullCalendar events
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(untagged)] // https://serde.rs/enum-reeapresentations.html
 pub enum FCStart {
     /// Timestamp with a date only.
@@ -81,6 +427,131 @@ pub enum FCStart {
     DateTime(NaiveDateTime),
 }
 
+impl FCStart {
+    /// The start as a date and time (midnight for a date only)
+    pub fn to_naive_date_time(&self) -> NaiveDateTime {
+        match self {
+            FCStart::Day(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+            FCStart::DateTime(datetime) => *datetime,
+        }
+    }
+}
+
+/// Same text as the JSON output: "2022-07-25" or "2022-07-25T18:00:00"
+impl fmt::Display for FCStart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FCStart::Day(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            FCStart::DateTime(datetime) => write!(f, "{}", datetime.format("%Y-%m-%dT%H:%M:%S")),
+        }
+    }
+}
+
+/// A date sorts before a date and time at midnight of the same day.
+impl Ord for FCStart {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let rank = |start: &FCStart| match start {
+            FCStart::Day(..) => 0,
+            FCStart::DateTime(..) => 1,
+        };
+        self.to_naive_date_time()
+            .cmp(&other.to_naive_date_time())
+            .then_with(|| rank(self).cmp(&rank(other)))
+    }
+}
+
+impl PartialOrd for FCStart {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Which Org data an event is made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Deadline,
+    Scheduled,
+    Closed,
+    /// N-th CLOCK line of a headline (from 0, in document order)
+    Clock(usize),
+    /// N-th active timestamp in the title and section of a headline
+    Timestamp(usize),
+    /// N-th inactive timestamp in the title and section of a headline
+    Inactive(usize),
+}
+
+impl EventKind {
+    /// Put in front of the title by the functions of this module: the
+    /// English prefix, which can be changed, see [`crate::prefix`].
+    pub fn prefix(&self) -> &'static str {
+        default_prefix(TimestampKind::from(*self))
+    }
+
+    /// Is the TODO keyword in the title? Not for CLOCK lines, which are
+    /// the work done rather than the task.
+    pub fn shows_keyword(&self) -> bool {
+        !matches!(self, EventKind::Clock(..))
+    }
+
+    /// Suffix which tells apart events of the same headline
+    fn id_suffix(&self) -> String {
+        match self {
+            EventKind::Deadline => "DL".to_string(),
+            EventKind::Scheduled => "SCL".to_string(),
+            EventKind::Closed => "CLS".to_string(),
+            EventKind::Clock(n) => format!("CLK{}", n),
+            EventKind::Timestamp(n) => format!("TS{}", n),
+            EventKind::Inactive(n) => format!("IA{}", n),
+        }
+    }
+}
+
+/// Make a stable event id.
+///
+/// The headline's `:ID:` (or `:CUSTOM_ID:`) is used when it has one,
+/// otherwise a hash of the file path, which should be that of [`id_path`],
+/// the outline path and the start, and of `duplicate`, the number of earlier headlines with the same
+/// outline path, when there are some. Either is followed by the kind, e.g.
+/// "2e8a1478-...-SCL" or "9c1d...-CLK0". Without an ID, the ids change when
+/// the file is moved.
+pub fn make_id(
+    title: &elements::Title,
+    outline_path: &[String],
+    duplicate: usize,
+    kind: EventKind,
+    start: &FCStart,
+    file_path: &str,
+) -> String {
+    let base = match make_property(title, "ID").or_else(|| make_property(title, "CUSTOM_ID")) {
+        Some(id) => id,
+        None => {
+            let outline_path = outline_path.join("\u{1f}");
+            let start = start.to_string();
+            let duplicate = duplicate.to_string();
+            let mut parts = vec![file_path, &outline_path, &start];
+            if duplicate != "0" {
+                parts.push(&duplicate);
+            }
+            format!("{:016x}", stable_hash_all(&parts))
+        }
+    };
+    format!("{}-{}", base, kind.id_suffix())
+}
+
+/// The path of a file as the ids take it, the same for `notes.org`,
+/// `./notes.org` and `/home/me/notes.org`; the path as given when it cannot
+/// be resolved
+pub fn id_path(file_path: &str) -> String {
+    fs::canonicalize(file_path)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| file_path.to_string())
+}
+
+/// Id of one occurrence of a repeating event, e.g. "...-SCL@2022-07-28T17:00:00"
+pub fn occurrence_id(id: &str, start: &FCStart) -> String {
+    format!("{}@{}", id, start)
+}
+
 /// Convert Org reeater to FC RRule
 fn repeater_to_fc_rrule(
     start: &orgize::elements::Datetime,
@@ -113,11 +584,7 @@ fn repeater_to_fc_rrule(
             dtstart = FCStart::Day(start.into());
         }
     }
-    Some(FCRRule {
-        dtstart: dtstart,
-        freq: timeunit,
-        interval: repeater.value,
-    })
+    Some(FCRRule::new(dtstart, &timeunit, repeater.value))
 }
 
 /// Make a title with a keyword
@@ -155,34 +622,52 @@ pub fn make_description(title: &&elements::Title) -> Option<String> {
     Some(description)
 }
 
-/// Make a event's "color"
-pub fn make_color(title: &elements::Title) -> Option<String> {
-    let mut color: String = String::new();
+/// Get the value of a property. The last one wins when it is repeated.
+pub fn make_property(title: &elements::Title, key: &str) -> Option<String> {
+    let mut value: String = String::new();
     for (k, v) in title.properties.clone().into_iter() {
-        if k == "FC_BG_COLOR" {
-            color = v.to_string();
+        if k == key {
+            value = v.to_string();
         }
     }
-    if color == "" {
+    let value = value.trim();
+    if value.is_empty() {
         return None;
     }
-    Some(color)
+    Some(value.to_string())
 }
 
-/// Make event's text color
+/// Make a event's "color", None when it is not a color
+pub fn make_color(title: &elements::Title) -> Option<String> {
+    make_property(title, "FC_BG_COLOR").and_then(|value| normalize_color(&value).ok())
+}
+
+/// Make event's text color, None when it is not a color
 pub fn make_text_color(title: &elements::Title) -> Option<String> {
-    let mut text_color: String = String::new();
+    make_property(title, "FC_TXT_COLOR").and_then(|value| normalize_color(&value).ok())
+}
+
+/// Make FC "extendedProps" from the properties which are not consumed by
+/// FullCalendar fields (DESCRIPTION and the FC_ namespace).
+/// A repeated key ("KEY+") is joined with a space as Org does.
+pub fn make_extended_props(title: &elements::Title) -> Map<String, Value> {
+    let mut properties: Map<String, Value> = Map::new();
     for (k, v) in title.properties.clone().into_iter() {
-        if k == "FC_TXT_COLOR" {
-            text_color = v.to_string();
+        if k == "DESCRIPTION" || k.starts_with("FC_") {
+            continue;
         }
+        let value = match properties.get(k.as_ref()) {
+            Some(Value::String(prev)) => format!("{} {}", prev, v),
+            _ => v.to_string(),
+        };
+        properties.insert(k.to_string(), Value::String(value));
     }
 
-    if text_color == "" {
-        return None;
+    let mut extended_props: Map<String, Value> = Map::new();
+    if !properties.is_empty() {
+        extended_props.insert("properties".to_string(), Value::Object(properties));
     }
-
-    Some(text_color)
+    extended_props
 }
 
 pub fn make_rrule(
@@ -224,17 +709,14 @@ fn make_event_from_dl_or_scl_wo_range(
     prefix: &str,
     file_path: &str,
 ) -> FCEvent {
-    FCEvent::new(
-        make_title_with_keyword(&title, prefix),
-        make_start(start),
-        None,
-        None,
-        make_description(&title),
-        make_rrule(start, repeater),
-        make_color(&title),
-        make_text_color(&title),
-        make_file_path(&file_path),
-    )
+    FCEvent::builder(make_title_with_keyword(title, prefix), make_start(start))
+        .description(make_description(&title))
+        .rrule(make_rrule(start, repeater))
+        .color(make_color(title))
+        .text_color(make_text_color(title))
+        .file_path(make_file_path(file_path))
+        .extended_props(make_extended_props(title))
+        .build()
 }
 
 pub fn make_event_from_deadline_wo_range(
@@ -243,7 +725,13 @@ pub fn make_event_from_deadline_wo_range(
     repeater: &Option<elements::Repeater>,
     file_path: &str,
 ) -> FCEvent {
-    make_event_from_dl_or_scl_wo_range(&title, &start, &repeater, "DL: ", file_path)
+    make_event_from_dl_or_scl_wo_range(
+        &title,
+        &start,
+        &repeater,
+        EventKind::Deadline.prefix(),
+        file_path,
+    )
 }
 
 pub fn make_event_from_scheduled_wo_range(
@@ -252,7 +740,13 @@ pub fn make_event_from_scheduled_wo_range(
     repeater: &Option<elements::Repeater>,
     file_path: &str,
 ) -> FCEvent {
-    make_event_from_dl_or_scl_wo_range(&title, &start, &repeater, "SCL: ", file_path)
+    make_event_from_dl_or_scl_wo_range(
+        &title,
+        &start,
+        &repeater,
+        EventKind::Scheduled.prefix(),
+        file_path,
+    )
 }
 
 fn make_event_from_dl_or_scl_with_range(
@@ -270,17 +764,19 @@ fn make_event_from_dl_or_scl_with_range(
         Some(datetime_duration(start, end))
     };
 
-    FCEvent::new(
+    FCEvent::builder(
         make_title_with_keyword(title, prefix),
         FCStart::DateTime(start.into()),
-        Some(end.into()),
-        duration,
-        make_description(&title),
-        make_rrule(&start, &start_repeater),
-        make_color(&title),
-        make_text_color(&title),
-        make_file_path(&file_path),
     )
+    .end(Some(end.into()))
+    .duration(duration)
+    .description(make_description(&title))
+    .rrule(rrule)
+    .color(make_color(title))
+    .text_color(make_text_color(title))
+    .file_path(make_file_path(file_path))
+    .extended_props(make_extended_props(title))
+    .build()
 }
 
 pub fn make_event_from_deadline_with_range(
@@ -290,7 +786,14 @@ pub fn make_event_from_deadline_with_range(
     repeater: &Option<elements::Repeater>,
     file_path: &str,
 ) -> FCEvent {
-    make_event_from_dl_or_scl_with_range(&title, &start, &end, &repeater, "DL: ", file_path)
+    make_event_from_dl_or_scl_with_range(
+        &title,
+        &start,
+        &end,
+        &repeater,
+        EventKind::Deadline.prefix(),
+        file_path,
+    )
 }
 
 pub fn make_event_from_scheduled_with_range(
@@ -300,7 +803,47 @@ pub fn make_event_from_scheduled_with_range(
     repeater: &Option<elements::Repeater>,
     file_path: &str,
 ) -> FCEvent {
-    make_event_from_dl_or_scl_with_range(&title, &start, &end, &repeater, "SCL: ", file_path)
+    make_event_from_dl_or_scl_with_range(
+        &title,
+        &start,
+        &end,
+        &repeater,
+        EventKind::Scheduled.prefix(),
+        file_path,
+    )
+}
+
+/// Make an event at the CLOSED time of a headline
+pub fn make_event_from_closed(
+    title: &elements::Title,
+    closed: &elements::Datetime,
+    file_path: &str,
+) -> FCEvent {
+    make_event_from_dl_or_scl_wo_range(title, closed, &None, EventKind::Closed.prefix(), file_path)
+}
+
+/// Make an event from a timestamp in the text, with or without a range
+pub fn make_event_from_timestamp(
+    title: &elements::Title,
+    start: &elements::Datetime,
+    end: Option<&elements::Datetime>,
+    repeater: &Option<elements::Repeater>,
+    kind: EventKind,
+    file_path: &str,
+) -> FCEvent {
+    match end {
+        Some(end) => make_event_from_dl_or_scl_with_range(
+            title,
+            start,
+            end,
+            repeater,
+            kind.prefix(),
+            file_path,
+        ),
+        None => {
+            make_event_from_dl_or_scl_wo_range(title, start, repeater, kind.prefix(), file_path)
+        }
+    }
 }
 
 /// Make events from CLOCK lines
@@ -310,15 +853,16 @@ pub fn make_event_from_clocks(
     end: &elements::Datetime,
     file_path: &str,
 ) -> FCEvent {
-    FCEvent::new(
-        make_title_without_keyword(&title, ""),
+    FCEvent::builder(
+        make_title_without_keyword(title, ""),
         FCStart::DateTime(start.into()),
-        Some(end.into()),
-        Some(datetime_duration(start, end)),
-        make_description(&title),
-        None,
-        make_color(&title),
-        make_text_color(&title),
-        make_file_path(&file_path),
     )
+    .end(Some(end.into()))
+    .duration(Some(datetime_duration(start, end)))
+    .description(make_description(&title))
+    .color(make_color(title))
+    .text_color(make_text_color(title))
+    .file_path(make_file_path(file_path))
+    .extended_props(make_extended_props(title))
+    .build()
 }
@@ -0,0 +1,37 @@
+//! Hashing that stays the same across runs, platforms and Rust versions.
+//! (`std::collections::hash_map::DefaultHasher` makes no such promise.)
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a hash of a string
+pub fn stable_hash(data: &str) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in data.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Hash some strings as one, separated so that ("ab", "c") != ("a", "bc")
+pub fn stable_hash_all(parts: &[&str]) -> u64 {
+    stable_hash(&parts.join("\u{1f}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(stable_hash("foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn parts_are_separated() {
+        assert_ne!(stable_hash_all(&["ab", "c"]), stable_hash_all(&["a", "bc"]));
+    }
+}
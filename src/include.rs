@@ -0,0 +1,476 @@
+//! `#+INCLUDE:` and `#+SETUPFILE:` lines of Org files, replaced by the text
+//! of the files they name before the text is parsed.
+//!
+//! - `#+SETUPFILE: common.setup` brings in the `#+` lines of the setup file
+//!   (TODO keywords, category, properties, ...) and those of its own setup
+//!   files. Remote setup files (`https://...`) are left alone.
+//! - `#+INCLUDE: "projects.org"` brings in the file, its headlines demoted
+//!   below the headline the line is in, or to `:minlevel 2`; `:lines "5-10"`
+//!   keeps a range of lines. `#+INCLUDE: "script.sh" src sh`, `example`,
+//!   `quote` and the like wrap the text in a block.
+//!
+//! Names are relative to the directory of the file they are in. A file
+//! including itself, directly or not, is an error. The expanded text keeps
+//! where each of its lines comes from, so that events report the file and
+//! the position of their headline.
+use crate::agenda::expand_path;
+use crate::hash::stable_hash;
+use crate::outline::{headline_level, Position};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Blocks whose lines starting with `*` or `#+` are escaped with a comma
+const ESCAPED_BLOCKS: &[&str] = &["SRC", "EXAMPLE", "EXPORT"];
+
+/// A file with some lines in the expanded text
+#[derive(Debug)]
+struct SourceFile {
+    path: String,
+    /// [`stable_hash`] of its text
+    hash: u64,
+    /// Byte offsets of the starts of its lines
+    line_starts: Vec<usize>,
+}
+
+/// Lines of the expanded text coming one after the other from a file
+#[derive(Debug)]
+struct Segment {
+    /// First line in the expanded text, from 0
+    line: usize,
+    file: usize,
+    /// First line in the file, from 0
+    source_line: usize,
+}
+
+/// The text of a file with its included and setup files
+#[derive(Debug)]
+pub struct Expanded {
+    pub text: String,
+    files: Vec<SourceFile>,
+    segments: Vec<Segment>,
+    /// Number of lines of the text
+    lines: usize,
+    /// Level of the last headline of the text
+    level: usize,
+    /// Canonical paths of the files being included, against cycles
+    stack: Vec<PathBuf>,
+}
+
+impl Expanded {
+    /// File and position of a position in the expanded text
+    pub fn locate(&self, position: Position) -> (&str, Position) {
+        let line = position.line - 1;
+        let index = self
+            .segments
+            .partition_point(|segment| segment.line <= line)
+            .saturating_sub(1);
+        let segment = match self.segments.get(index) {
+            Some(segment) => segment,
+            None => return ("", position),
+        };
+        let file = &self.files[segment.file];
+        let source_line = segment.source_line + line - segment.line;
+        let offset = file
+            .line_starts
+            .get(source_line)
+            .copied()
+            .unwrap_or_default();
+        (
+            &file.path,
+            Position {
+                line: source_line + 1,
+                offset,
+            },
+        )
+    }
+
+    /// The files read and the hashes of their texts, the converted one first
+    pub fn sources(&self) -> Vec<(&str, u64)> {
+        let mut sources: Vec<(&str, u64)> = Vec::new();
+        for file in &self.files {
+            if !sources.iter().any(|(path, _)| *path == file.path) {
+                sources.push((&file.path, file.hash));
+            }
+        }
+        sources
+    }
+
+    fn push_line(&mut self, line: &str, file: usize, source_line: usize) {
+        let follows = self.segments.last().is_some_and(|segment| {
+            segment.file == file && segment.source_line + self.lines - segment.line == source_line
+        });
+        if !follows {
+            self.segments.push(Segment {
+                line: self.lines,
+                file,
+                source_line,
+            });
+        }
+        if let Some(level) = headline_level(line) {
+            self.level = level;
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.lines += 1;
+    }
+
+    /// Read a file and add its `lines`, with its highest headlines at level
+    /// `min_level`
+    fn add_file(
+        &mut self,
+        path: &str,
+        lines: Option<(usize, usize)>,
+        min_level: Option<usize>,
+    ) -> Result<(), String> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        if self.stack.contains(&canonical) {
+            let mut cycle: Vec<String> = self
+                .stack
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            cycle.push(canonical.display().to_string());
+            return Err(format!("include cycle: {}", cycle.join(" -> ")));
+        }
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let file = self.add_source(path, &text);
+        let (first, last) = lines.unwrap_or((0, usize::MAX));
+        let mut kept: Vec<(usize, Cow<str>)> = text
+            .lines()
+            .enumerate()
+            .skip(first)
+            .take(last.saturating_sub(first))
+            .map(|(index, line)| (index, Cow::Borrowed(line)))
+            .collect();
+        let highest = kept
+            .iter()
+            .filter_map(|(_, line)| headline_level(line))
+            .min();
+        if let (Some(min_level), Some(highest)) = (min_level, highest) {
+            for (_, line) in kept.iter_mut() {
+                if let Some(level) = headline_level(line) {
+                    let level = (level + min_level).saturating_sub(highest).max(1);
+                    let words = line.trim_start_matches('*').to_string();
+                    *line = Cow::Owned(format!("{}{}", "*".repeat(level), words));
+                }
+            }
+        }
+        self.stack.push(canonical);
+        let result = self.add_lines(path, file, &kept);
+        self.stack.pop();
+        result
+    }
+
+    fn add_source(&mut self, path: &str, text: &str) -> usize {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(index, _)| index + 1));
+        self.files.push(SourceFile {
+            path: path.to_string(),
+            hash: stable_hash(text),
+            line_starts,
+        });
+        self.files.len() - 1
+    }
+
+    /// Add the lines of a file, expanding its `#+INCLUDE:` and
+    /// `#+SETUPFILE:` lines
+    fn add_lines(
+        &mut self,
+        path: &str,
+        file: usize,
+        lines: &[(usize, Cow<str>)],
+    ) -> Result<(), String> {
+        for (index, line) in lines {
+            let (index, line) = (*index, line.as_ref());
+            let located = |error: String| format!("{}:{}: {}", path, index + 1, error);
+            let keyword = keyword(line);
+            match keyword.as_ref().map(|(key, value)| (key.as_str(), *value)) {
+                Some(("SETUPFILE", value)) => {
+                    let name = unquote(value);
+                    if name.contains("://") {
+                        self.push_line(line, file, index);
+                    } else {
+                        let setup = resolve(path, name);
+                        self.add_setup_file(&setup).map_err(located)?;
+                    }
+                }
+                Some(("INCLUDE", value)) => {
+                    let include = Include::parse(value).map_err(located)?;
+                    let included = resolve(path, &include.file);
+                    match &include.block {
+                        Some(block) => self
+                            .add_block(&included, block, include.lines, file, index)
+                            .map_err(located)?,
+                        None => {
+                            let min_level = include.min_level.unwrap_or(self.level + 1);
+                            self.add_file(&included, include.lines, Some(min_level))
+                                .map_err(located)?
+                        }
+                    }
+                }
+                _ => self.push_line(line, file, index),
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the `#+` lines of a setup file, and of its setup files
+    fn add_setup_file(&mut self, path: &str) -> Result<(), String> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        if self.stack.contains(&canonical) {
+            return Err(format!("setup file cycle: {}", canonical.display()));
+        }
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let file = self.add_source(path, &text);
+        self.stack.push(canonical);
+        let lines: Vec<(usize, Cow<str>)> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| keyword(line).is_some_and(|(key, _)| key != "INCLUDE"))
+            .map(|(index, line)| (index, Cow::Borrowed(line)))
+            .collect();
+        let result = self.add_lines(path, file, &lines);
+        self.stack.pop();
+        result
+    }
+
+    /// Add a file in a block, e.g. `#+BEGIN_SRC sh`
+    fn add_block(
+        &mut self,
+        path: &str,
+        block: &str,
+        lines: Option<(usize, usize)>,
+        includer: usize,
+        index: usize,
+    ) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let file = self.add_source(path, &text);
+        let (name, parameters) = block.split_once(' ').unwrap_or((block, ""));
+        let name = name.to_uppercase();
+        let begin = format!("#+BEGIN_{} {}", name, parameters);
+        self.push_line(begin.trim_end(), includer, index);
+        let (first, last) = lines.unwrap_or((0, usize::MAX));
+        let escape = ESCAPED_BLOCKS.contains(&name.as_str());
+        for (source_line, line) in text.lines().enumerate() {
+            if source_line < first || source_line >= last {
+                continue;
+            }
+            let trimmed = line.trim_start();
+            if escape && (trimmed.starts_with('*') || trimmed.starts_with("#+")) {
+                self.push_line(&format!(",{}", line), file, source_line);
+            } else {
+                self.push_line(line, file, source_line);
+            }
+        }
+        self.push_line(&format!("#+END_{}", name), includer, index);
+        Ok(())
+    }
+}
+
+/// An `#+INCLUDE:` line
+#[derive(Debug, PartialEq, Eq)]
+struct Include {
+    file: String,
+    /// `src sh`, `example`, ...
+    block: Option<String>,
+    min_level: Option<usize>,
+    /// Lines from, to, from 0 and the last excluded
+    lines: Option<(usize, usize)>,
+}
+
+impl Include {
+    fn parse(value: &str) -> Result<Include, String> {
+        let value = value.trim();
+        let (file, rest) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| format!("unclosed quote in \"{}\"", value))?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => value.split_once(' ').unwrap_or((value, "")),
+        };
+        if file.is_empty() {
+            return Err("no file to include".to_string());
+        }
+        if file.contains("::") {
+            return Err(format!(
+                "searches in included files are not supported: \"{}\"",
+                file
+            ));
+        }
+        let (block, options) = match rest.find(" :") {
+            Some(index) => (rest[..index].trim(), &rest[index..]),
+            None if rest.trim_start().starts_with(':') => ("", rest),
+            None => (rest.trim(), ""),
+        };
+        let mut include = Include {
+            file: file.to_string(),
+            block: Some(block.to_string()).filter(|block| !block.is_empty()),
+            min_level: None,
+            lines: None,
+        };
+        let mut words = options.split_whitespace();
+        while let Some(word) = words.next() {
+            let argument = words.next().map(unquote).unwrap_or_default();
+            match word {
+                ":minlevel" => {
+                    let level = argument
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|level| *level > 0)
+                        .ok_or_else(|| format!("invalid :minlevel \"{}\"", argument))?;
+                    include.min_level = Some(level);
+                }
+                ":lines" => include.lines = Some(parse_lines(argument)?),
+                _ => {}
+            }
+        }
+        Ok(include)
+    }
+}
+
+/// `"5-10"`, `"5-"` or `"-10"`: lines 5 to 10, from 1
+fn parse_lines(range: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid :lines \"{}\"", range);
+    let (from, to) = range.split_once('-').ok_or_else(invalid)?;
+    let number = |text: &str, default: usize| match text.trim() {
+        "" => Ok(default),
+        text => text.parse::<usize>().map_err(|_| invalid()),
+    };
+    let from = number(from, 1)?.max(1);
+    let to = number(to, usize::MAX)?;
+    Ok((from - 1, to))
+}
+
+/// Key in upper case and value of a `#+KEY: value` line
+fn keyword(line: &str) -> Option<(String, &str)> {
+    let rest = line.trim_start().strip_prefix("#+")?;
+    let (key, value) = rest.split_once(':')?;
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key.to_uppercase(), value.trim()))
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// A file named in a file, relative to its directory
+fn resolve(including: &str, name: &str) -> String {
+    let base = Path::new(including).parent().unwrap_or(Path::new(""));
+    expand_path(name, base).to_string_lossy().into_owned()
+}
+
+/// Read an Org file and the files it includes, named from `file_path`
+pub fn expand_includes(file_path: &str) -> Result<Expanded, String> {
+    let mut expanded = Expanded {
+        text: String::new(),
+        files: Vec::new(),
+        segments: Vec::new(),
+        lines: 0,
+        level: 0,
+        stack: Vec::new(),
+    };
+    expanded.add_file(file_path, None, None)?;
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn expand_setup_and_include_files() {
+        let dir = TempDir::new(
+            "include",
+            &[
+                (
+                    "main.org",
+                    "#+SETUPFILE: common.setup\n* Projects\n#+INCLUDE: \"projects.org\"\n* Scripts\n#+INCLUDE: run.sh src sh :lines \"2-\"\n",
+                ),
+                ("common.setup", "Notes\n#+TODO: TODO WAIT | DONE\n#+CATEGORY: work\n"),
+                ("projects.org", "#+TITLE: Projects\n* Alpha\n** Review\n"),
+                ("run.sh", "#!/bin/sh\n* not a headline\n"),
+            ],
+        );
+        let main = dir.path("main.org");
+        let expanded = expand_includes(&main).unwrap();
+        assert_eq!(
+            expanded.text,
+            "#+TODO: TODO WAIT | DONE\n#+CATEGORY: work\n* Projects\n#+TITLE: Projects\n** Alpha\n*** Review\n* Scripts\n#+BEGIN_SRC sh\n,* not a headline\n#+END_SRC\n"
+        );
+        let locate = |line| expanded.locate(Position { line, offset: 0 });
+        assert_eq!(
+            locate(3),
+            (
+                main.as_str(),
+                Position {
+                    line: 2,
+                    offset: 26
+                }
+            )
+        );
+        let projects = dir.path("projects.org");
+        assert_eq!(
+            locate(6),
+            (
+                projects.as_str(),
+                Position {
+                    line: 3,
+                    offset: 26
+                }
+            )
+        );
+        assert_eq!(
+            locate(7),
+            (
+                main.as_str(),
+                Position {
+                    line: 4,
+                    offset: 63
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn detect_cycles() {
+        let dir = TempDir::new(
+            "cycle",
+            &[
+                ("a.org", "* A\n#+INCLUDE: \"b.org\" :minlevel 1\n"),
+                ("b.org", "* B\n#+include: a.org\n"),
+            ],
+        );
+        let error = expand_includes(&dir.path("a.org")).unwrap_err();
+        assert!(error.contains("include cycle"), "{}", error);
+        assert!(expand_includes(&dir.path("missing.org")).is_err());
+    }
+
+    #[test]
+    fn parse_include_lines() {
+        assert_eq!(
+            Include::parse("\"my notes.org\" :minlevel 2 :lines \"5-10\"").unwrap(),
+            Include {
+                file: "my notes.org".to_string(),
+                block: None,
+                min_level: Some(2),
+                lines: Some((4, 10)),
+            }
+        );
+        assert_eq!(
+            Include::parse("run.sh src sh").unwrap().block.as_deref(),
+            Some("src sh")
+        );
+        assert!(Include::parse("\"notes.org::*Review\"").is_err());
+        assert!(Include::parse("notes.org :lines \"x\"").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Property inheritance down the outline, as in Org with
+//! `org-use-property-inheritance`.
+//!
+//! A headline without a property takes it from its nearest ancestor which
+//! has it, if the property is inherited, and then from the file's
+//! `#+PROPERTY:` lines. By default only the style properties are inherited,
+//! so a color set on a project heading applies to all of its tasks.
+use crate::outline::Outline;
+use crate::settings::FileSettings;
+use orgize::elements::PropertiesMap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Properties inherited when nothing else is configured
+pub const DEFAULT_INHERITED_PROPERTIES: &[&str] = &[
+    "FC_BG_COLOR",
+    "FC_TXT_COLOR",
+    "FC_BACKGROUND_COLOR",
+    "FC_BORDER_COLOR",
+    "FC_CLASS",
+];
+
+/// Properties identifying a headline, which are never inherited
+pub const NEVER_INHERITED_PROPERTIES: &[&str] = &["ID", "CUSTOM_ID", "FC_ID"];
+
+/// Which properties are inherited: `true` (all), `false` (none) or a list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyInheritance {
+    All(bool),
+    Only(Vec<String>),
+}
+
+impl Default for PropertyInheritance {
+    fn default() -> PropertyInheritance {
+        PropertyInheritance::Only(
+            DEFAULT_INHERITED_PROPERTIES
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+        )
+    }
+}
+
+impl PropertyInheritance {
+    pub fn inherits(&self, key: &str) -> bool {
+        if NEVER_INHERITED_PROPERTIES
+            .iter()
+            .any(|never| never.eq_ignore_ascii_case(key))
+        {
+            return false;
+        }
+        match self {
+            PropertyInheritance::All(all) => *all,
+            PropertyInheritance::Only(keys) => keys.iter().any(|k| k.eq_ignore_ascii_case(key)),
+        }
+    }
+}
+
+fn has_key(properties: &PropertiesMap, key: &str) -> bool {
+    properties.iter().any(|(k, _)| k.eq_ignore_ascii_case(key))
+}
+
+/// Properties of the current headline of an outline with the inherited ones
+/// and the file's defaults added, None when there is nothing to add
+pub fn inherited_properties<'a>(
+    outline: &Outline<'a>,
+    settings: &FileSettings,
+    inheritance: &PropertyInheritance,
+) -> Option<PropertiesMap<'a>> {
+    let title = outline.current()?;
+    let mut properties = title.properties.clone();
+    let mut added = false;
+
+    // The nearest ancestor first
+    for ancestor in outline.ancestors().iter().rev() {
+        for (key, _) in ancestor.properties.iter() {
+            if !inheritance.inherits(key) || has_key(&properties, key) {
+                continue;
+            }
+            // The last value, as in make_property
+            let value = ancestor
+                .properties
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .last()
+                .unwrap_or_default();
+            properties.pairs.push((key.clone(), value));
+            added = true;
+        }
+    }
+
+    for (key, value) in &settings.properties {
+        if NEVER_INHERITED_PROPERTIES
+            .iter()
+            .any(|never| never.eq_ignore_ascii_case(key))
+            || has_key(&properties, key)
+        {
+            continue;
+        }
+        properties
+            .pairs
+            .push((Cow::Owned(key.clone()), Cow::Owned(value.clone())));
+        added = true;
+    }
+
+    if added {
+        Some(properties)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inherit_configured_properties() {
+        let default = PropertyInheritance::default();
+        assert!(default.inherits("FC_BG_COLOR"));
+        assert!(default.inherits("fc_bg_color"));
+        assert!(!default.inherits("LOCATION"));
+        assert!(PropertyInheritance::All(true).inherits("LOCATION"));
+        assert!(!PropertyInheritance::All(true).inherits("ID"));
+        assert!(!PropertyInheritance::All(false).inherits("FC_BG_COLOR"));
+
+        let only: PropertyInheritance = serde_json::from_str(r#"["LOCATION"]"#).unwrap();
+        assert!(only.inherits("LOCATION"));
+        let all: PropertyInheritance = serde_json::from_str("true").unwrap();
+        assert_eq!(all, PropertyInheritance::All(true));
+    }
+}
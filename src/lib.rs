@@ -1,3 +1,31 @@
+pub mod agenda;
+pub mod body;
+pub mod category;
+pub mod color;
+pub mod color_names;
+pub mod config;
+pub mod diagnostic;
+pub mod editor;
+pub mod filter;
 pub mod fullcalendar;
+pub mod hash;
+pub mod include;
+pub mod inheritance;
+pub mod markup;
+pub mod options;
+pub mod outline;
 pub mod parse;
+pub mod prefix;
+pub mod priority;
+pub mod properties;
+pub mod query;
+pub mod rrule;
+pub mod scan;
+pub mod settings;
+pub mod tags;
+pub mod template;
+#[cfg(test)]
+mod testing;
 pub mod timestamp;
+pub mod todo;
+pub mod walk;
@@ -0,0 +1,379 @@
+//! Org markup in headline titles: links, emphasis, statistics cookies and
+//! timestamps, which the titles carry as they are written (`Title.raw`).
+//!
+//! A title is rendered as
+//!
+//! - raw: as written, `Fix [[https://x/42][Ticket 42]] *now* [1/3]`
+//! - plain text: links reduced to their descriptions (or targets), emphasis
+//!   markers and timestamps left out, `Fix Ticket 42 now`
+//! - HTML: the plain text in `title` and, in `extendedProps.titleHtml`,
+//!   `Fix <a href="https://x/42">Ticket 42</a> <b>now</b>` for FullCalendar's
+//!   `eventContent`
+//!
+//! Statistics cookies (`[1/3]`, `[33%]`) are kept on request. Links to
+//! other schemes than those of [`WEB_SCHEMES`], like `javascript:` or
+//! `file:`, are rendered as their descriptions only.
+use serde::{Deserialize, Serialize};
+
+/// Characters which may come before an emphasis marker
+const PRE_EMPHASIS: &str = "-({'\"";
+/// Characters which may come after an emphasis marker
+const POST_EMPHASIS: &str = "-.,;:!?')}[\"\\";
+
+/// URL schemes of the links which are written as links, and can become the
+/// event's `url`
+pub const WEB_SCHEMES: &[&str] = &[
+    "http", "https", "mailto", "ftp", "ftps", "tel", "sms", "geo",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleMarkup {
+    /// As written
+    #[default]
+    Raw,
+    Plain,
+    /// Plain text in `title`, HTML in `extendedProps.titleHtml`
+    Html,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TitleOptions {
+    pub markup: TitleMarkup,
+    /// Keep statistics cookies like `[2/5]` in plain text and HTML
+    pub cookies: bool,
+    /// Make the first web link of the title the event's `url`, unless
+    /// `FC_URL` sets one
+    pub link_url: bool,
+}
+
+/// A piece of a title
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inline<'a> {
+    Text(&'a str),
+    Link {
+        target: &'a str,
+        description: Option<&'a str>,
+    },
+    /// `*bold*`, `/italic/`, `_underline_` or `+strike-through+`
+    Emphasis(char, &'a str),
+    /// `=verbatim=` or `~code~`
+    Code(&'a str),
+    Cookie(&'a str),
+    Timestamp(&'a str),
+}
+
+/// Split a text into its pieces
+fn parse(text: &str) -> Vec<Inline<'_>> {
+    let mut inlines: Vec<Inline> = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let previous = text[..i].chars().next_back();
+        let found = link(rest)
+            .or_else(|| cookie(rest))
+            .or_else(|| timestamp(rest))
+            .or_else(|| emphasis(rest, previous));
+        match found {
+            Some((inline, len)) => {
+                if text_start < i {
+                    inlines.push(Inline::Text(&text[text_start..i]));
+                }
+                inlines.push(inline);
+                i += len;
+                text_start = i;
+            }
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    if text_start < text.len() {
+        inlines.push(Inline::Text(&text[text_start..]));
+    }
+    inlines
+}
+
+/// `[[target][description]]` or `[[target]]`
+fn link(text: &str) -> Option<(Inline<'_>, usize)> {
+    let inner = text.strip_prefix("[[")?;
+    let end = inner.find("]]")?;
+    let (target, description) = match inner[..end].split_once("][") {
+        Some((target, description)) => (target, Some(description)),
+        None => (&inner[..end], None),
+    };
+    if target.is_empty() || target.contains(['[', ']']) {
+        return None;
+    }
+    Some((
+        Inline::Link {
+            target,
+            description,
+        },
+        end + 4,
+    ))
+}
+
+/// `[2/5]`, `[/]`, `[40%]` or `[%]`
+fn cookie(text: &str) -> Option<(Inline<'_>, usize)> {
+    let inner = text.strip_prefix('[')?;
+    let end = inner.find(']')?;
+    let value = &inner[..end];
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let is_cookie = match value.split_once('/') {
+        Some((done, total)) => digits(done) && digits(total),
+        None => value.strip_suffix('%').is_some_and(digits),
+    };
+    is_cookie.then(|| (Inline::Cookie(&text[..end + 2]), end + 2))
+}
+
+/// `<2022-08-01 Mon>`, `[2022-08-01 Mon 10:00]` or a range of two of them
+fn timestamp(text: &str) -> Option<(Inline<'_>, usize)> {
+    fn single(text: &str) -> Option<usize> {
+        let close = match text.chars().next()? {
+            '<' => '>',
+            '[' => ']',
+            _ => return None,
+        };
+        let date = text.get(1..11)?;
+        let is_date = date.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        });
+        if !is_date {
+            return None;
+        }
+        let end = text[11..].find([close, '\n'])? + 11;
+        text[end..].starts_with(close).then_some(end + 1)
+    }
+    let mut len = single(text)?;
+    if let Some(end) = text[len..].strip_prefix("--").and_then(single) {
+        len += 2 + end;
+    }
+    Some((Inline::Timestamp(&text[..len]), len))
+}
+
+/// An emphasized text, following Org's rules on the characters around the
+/// markers
+fn emphasis(text: &str, previous: Option<char>) -> Option<(Inline<'_>, usize)> {
+    let marker = text.chars().next().filter(|c| "*/_=~+".contains(*c))?;
+    if previous.is_some_and(|c| !c.is_whitespace() && !PRE_EMPHASIS.contains(c)) {
+        return None;
+    }
+    let inner = &text[1..];
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    for (end, _) in inner.match_indices(marker) {
+        let content = &inner[..end];
+        if content.is_empty() || content.ends_with(char::is_whitespace) || content.contains('\n') {
+            continue;
+        }
+        let next = inner[end + 1..].chars().next();
+        if next.is_some_and(|c| !c.is_whitespace() && !POST_EMPHASIS.contains(c)) {
+            continue;
+        }
+        let inline = match marker {
+            '=' | '~' => Inline::Code(content),
+            _ => Inline::Emphasis(marker, content),
+        };
+        return Some((inline, end + 2));
+    }
+    None
+}
+
+/// The text with its spaces collapsed, as when pieces were left out
+fn collapse_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Title as plain text
+pub fn plain_text(title: &str, cookies: bool) -> String {
+    let mut text = String::new();
+    for inline in parse(title) {
+        match inline {
+            Inline::Text(value) | Inline::Code(value) => text.push_str(value),
+            Inline::Link {
+                target,
+                description,
+            } => match description {
+                Some(description) => text.push_str(&plain_text(description, cookies)),
+                None => text.push_str(target),
+            },
+            Inline::Emphasis(_, content) => text.push_str(&plain_text(content, cookies)),
+            Inline::Cookie(value) if cookies => text.push_str(value),
+            Inline::Cookie(_) | Inline::Timestamp(_) => {}
+        }
+    }
+    collapse_spaces(&text)
+}
+
+/// Title as HTML
+pub fn html(title: &str, cookies: bool) -> String {
+    let mut html = String::new();
+    for inline in parse(title) {
+        match inline {
+            Inline::Text(value) => html.push_str(&escape_html(value)),
+            Inline::Code(value) => {
+                html.push_str(&format!("<code>{}</code>", escape_html(value)));
+            }
+            Inline::Link {
+                target,
+                description,
+            } => {
+                let description = match description {
+                    Some(description) => self::html(description, cookies),
+                    None => escape_html(target),
+                };
+                if !is_safe_link(target) {
+                    html.push_str(&description);
+                    continue;
+                }
+                html.push_str(&format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(target),
+                    description
+                ));
+            }
+            Inline::Emphasis(marker, content) => {
+                let tag = match marker {
+                    '*' => "b",
+                    '/' => "i",
+                    '_' => "u",
+                    _ => "del",
+                };
+                html.push_str(&format!(
+                    "<{0}>{1}</{0}>",
+                    tag,
+                    self::html(content, cookies)
+                ));
+            }
+            Inline::Cookie(value) if cookies => html.push_str(&escape_html(value)),
+            Inline::Cookie(_) | Inline::Timestamp(_) => {}
+        }
+    }
+    collapse_spaces(&html)
+}
+
+/// Target of the first web link of a title
+pub fn first_link(title: &str) -> Option<String> {
+    parse(title).into_iter().find_map(|inline| match inline {
+        Inline::Link { target, .. } => link_scheme(target)
+            .filter(|scheme| is_web_scheme(scheme))
+            .map(|_| target.to_string()),
+        _ => None,
+    })
+}
+
+/// The scheme of a link target, None for a target relative to the page,
+/// like `notes.html` or `#today`
+pub fn link_scheme(target: &str) -> Option<&str> {
+    let end = target.find([':', '/', '?', '#'])?;
+    target[end..].starts_with(':').then(|| &target[..end])
+}
+
+/// Is a scheme one of [`WEB_SCHEMES`]? The scheme must be written as it
+/// is: browsers ignore tabs and line breaks in `java\tscript:`, this does not.
+pub fn is_web_scheme(scheme: &str) -> bool {
+    WEB_SCHEMES
+        .iter()
+        .any(|web| web.eq_ignore_ascii_case(scheme))
+}
+
+/// Is a link target a web URL, or one relative to the page? Any other, like
+/// `javascript:alert(1)`, could run code.
+pub fn is_safe_link(target: &str) -> bool {
+    match link_scheme(target) {
+        Some(scheme) => is_web_scheme(scheme),
+        None => true,
+    }
+}
+
+/// Escape `&`, `<`, `>` and quotes
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TITLE: &str =
+        "Fix [[https://example.com/42][Ticket /42/]] *now* [1/3] <2022-08-01 Mon>--<2022-08-02 Tue>";
+
+    #[test]
+    fn render_plain_text() {
+        assert_eq!(plain_text(TITLE, false), "Fix Ticket 42 now");
+        assert_eq!(plain_text(TITLE, true), "Fix Ticket 42 now [1/3]");
+        assert_eq!(
+            plain_text("See [[file:notes.org]]", false),
+            "See file:notes.org"
+        );
+        assert_eq!(
+            plain_text("=a*b*c= and ~code~ [40%]", false),
+            "a*b*c and code"
+        );
+        // Not markup
+        assert_eq!(plain_text("2*3*4 a/b/c [x]", false), "2*3*4 a/b/c [x]");
+        assert_eq!(plain_text("* not bold *", false), "* not bold *");
+    }
+
+    #[test]
+    fn render_html() {
+        assert_eq!(
+            html(TITLE, false),
+            "Fix <a href=\"https://example.com/42\">Ticket <i>42</i></a> <b>now</b>"
+        );
+        assert_eq!(
+            html("<b> & _under_, +gone+", false),
+            "&lt;b&gt; &amp; <u>under</u>, <del>gone</del>"
+        );
+    }
+
+    #[test]
+    fn leave_out_unsafe_links() {
+        let rendered = html("Click [[javascript:alert(1)][*x*]]", false);
+        assert_eq!(rendered, "Click <b>x</b>");
+        assert!(!rendered.contains("href"));
+        assert_eq!(
+            html("[[JavaScript:alert(1)]]", false),
+            "JavaScript:alert(1)"
+        );
+        for target in [
+            "java\tscript:alert(1)",
+            "java\nscript:alert(1)",
+            "\njavascript:alert(1)",
+            " javascript:alert(1)",
+            "data:text/html,<b>",
+            "file:notes.org",
+        ] {
+            assert!(!is_safe_link(target), "{:?}", target);
+            let rendered = html(&format!("[[{}][x]]", target), false);
+            assert!(!rendered.contains("href"), "{}", rendered);
+        }
+        assert!(is_safe_link("https://example.com"));
+        assert!(is_safe_link("notes.org"));
+        assert!(is_safe_link("#today"));
+        assert!(is_safe_link("agenda/week?day=2:00"));
+    }
+
+    #[test]
+    fn promote_first_web_link() {
+        assert_eq!(
+            first_link("[[id:1234][Spec]] [[https://x.org/a][A]] [[https://x.org/b]]").as_deref(),
+            Some("https://x.org/a")
+        );
+        assert_eq!(first_link("[[file:notes.org][Notes]]"), None);
+    }
+}
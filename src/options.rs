@@ -0,0 +1,108 @@
+//! Options of a conversion from Org to FullCalendar events
+use crate::body::DescriptionOptions;
+use crate::category::ColorOptions;
+use crate::color::Color;
+use crate::editor::EditorLinkOptions;
+use crate::filter::Filter;
+use crate::inheritance::PropertyInheritance;
+use crate::markup::TitleOptions;
+use crate::outline::{HeadlineContext, MetadataOptions};
+use crate::prefix::PrefixOptions;
+use crate::priority::PriorityOptions;
+use crate::query::Query;
+use crate::rrule::ExpandOptions;
+use crate::settings::FileSettings;
+use crate::template::TemplateOptions;
+use crate::todo::TodoKeywords;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Color of greyed out done items
+pub const DEFAULT_DONE_COLOR: &str = "#9e9e9e";
+
+/// What becomes of the events of done (and cancelled) headlines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DoneItems {
+    #[default]
+    Show,
+    /// Shown in `done_color`
+    Grey,
+    /// Left out, but their CLOCK lines are kept
+    Hide,
+}
+
+/// Events made besides those of the DEADLINE, SCHEDULED and CLOCK lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimestampEvents {
+    /// At the CLOSED time of the headlines
+    pub closed: bool,
+    /// At the active timestamps of the titles and sections
+    pub active: bool,
+    /// At the inactive timestamps of the titles and sections, but not of
+    /// their drawers, e.g. the notes of the LOGBOOK
+    pub inactive: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConvertOptions {
+    /// Leave out events starting this many days ago or earlier (0: keep them)
+    pub ignore_before_days: i64,
+    /// Leave out events starting this many days from now or later (0: keep them)
+    pub ignore_after_days: i64,
+    /// Which headlines become events
+    pub filter: Filter,
+    /// Which headlines become events, in the query language of [`crate::query`]
+    pub query: Option<Query>,
+    /// Which properties headlines inherit from their ancestors
+    pub inherit_properties: PropertyInheritance,
+    /// TODO keywords of the files without `#+TODO:` lines
+    pub todo_keywords: TodoKeywords,
+    /// Color of the events per TODO keyword, e.g. `"WAIT": "orange"`
+    pub todo_colors: BTreeMap<String, Color>,
+    pub done_items: DoneItems,
+    /// Color of greyed out done items, [`DEFAULT_DONE_COLOR`] when None
+    pub done_color: Option<Color>,
+    /// Title marker, colors and rank of priorities
+    pub priority: PriorityOptions,
+    /// Palette colors per category or file, and text colors
+    pub colors: ColorOptions,
+    /// Rendering of the Org markup in titles
+    pub title: TitleOptions,
+    /// What the descriptions are made from
+    pub description: DescriptionOptions,
+    /// Templates of the titles and descriptions, unless the file sets its own
+    pub templates: TemplateOptions,
+    /// Prefixes of the titles per kind of timestamp
+    pub prefixes: PrefixOptions,
+    /// Events of the CLOSED lines and of the timestamps in the text
+    pub timestamp_events: TimestampEvents,
+    /// Outline path, position and ID of the headlines
+    pub metadata: MetadataOptions,
+    /// Links from the events to their headlines in the editor
+    pub editor_links: EditorLinkOptions,
+    /// Replace the repeating events with their occurrences, for the clients
+    /// without FullCalendar's rrule plugin
+    pub expand_recurring: Option<ExpandOptions>,
+}
+
+impl ConvertOptions {
+    pub fn new(ignore_before_days: i64, ignore_after_days: i64) -> ConvertOptions {
+        ConvertOptions {
+            ignore_before_days,
+            ignore_after_days,
+            ..ConvertOptions::default()
+        }
+    }
+
+    /// Does the headline become events? It must pass both the filter and the query.
+    pub fn accepts(&self, headline: &HeadlineContext, settings: &FileSettings) -> bool {
+        self.filter.accepts(headline, settings)
+            && self
+                .query
+                .as_ref()
+                .is_none_or(|query| query.matches(headline))
+    }
+}
@@ -0,0 +1,949 @@
+use crate::body::{property_description, BodyWriter, DescriptionOptions, DescriptionSource};
+use crate::category::category;
+use crate::color::contrast_text_color;
+use crate::diagnostic::Diagnostic;
+use crate::editor::EditorLinkOptions;
+use crate::fullcalendar::{id_path, make_id, EventKind, FCEvent, FCStart};
+use crate::inheritance::inherited_properties;
+use crate::markup::{escape_html, first_link, html, plain_text, TitleMarkup, TitleOptions};
+use crate::options::{ConvertOptions, DoneItems, DEFAULT_DONE_COLOR};
+use crate::prefix::{PrefixOptions, TimestampKind, KIND_CLASS_PREFIX};
+use crate::properties::Overrides;
+use crate::rrule::{align_exdate, until_end_of};
+use crate::settings::FileSettings;
+use crate::tags::{merge_tags, tag_class_name};
+use crate::template::Template;
+use crate::timestamp::format_naive_datetime;
+use chrono::NaiveDate;
+use orgize::elements;
+use orgize::elements::PropertiesMap;
+use orgize::{Event, Org};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// States which cancel a single occurrence when logged in the LOGBOOK
+const CANCELLED_KEYWORDS: &[&str] = &["CANCELLED", "CANCELED"];
+
+/// Titles from the top level down to the headline being visited.
+///
+/// `Org::iter()` yields headlines in document order, so the ancestors are
+/// the titles kept on a stack whose levels are lower than the current one.
+#[derive(Debug, Default)]
+pub struct Outline<'a> {
+    titles: Vec<&'a elements::Title<'a>>,
+}
+
+impl<'a> Outline<'a> {
+    pub fn new() -> Outline<'a> {
+        Outline { titles: Vec::new() }
+    }
+
+    /// Visit a headline: leave the previous headlines on the same or a deeper level.
+    pub fn enter(&mut self, title: &'a elements::Title<'a>) {
+        while let Some(last) = self.titles.last() {
+            if last.level < title.level {
+                break;
+            }
+            self.titles.pop();
+        }
+        self.titles.push(title);
+    }
+
+    /// The headline being visited
+    pub fn current(&self) -> Option<&'a elements::Title<'a>> {
+        self.titles.last().copied()
+    }
+
+    /// Ancestors of the current headline, the top level first
+    pub fn ancestors(&self) -> &[&'a elements::Title<'a>] {
+        match self.titles.split_last() {
+            Some((_, ancestors)) => ancestors,
+            None => &[],
+        }
+    }
+
+    /// Titles of the ancestors and the current headline
+    pub fn path(&self) -> Vec<String> {
+        self.titles
+            .iter()
+            .map(|title| title.raw.to_string())
+            .collect()
+    }
+}
+
+/// Where a headline starts in its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// From 1
+    pub line: usize,
+    /// Byte offset, from 0
+    pub offset: usize,
+}
+
+/// Positions of the headlines of an Org text, in document order.
+///
+/// A line of stars and a blank is always a headline in Org, even in a block,
+/// so the n-th of them is the n-th headline orgize yields.
+pub fn headline_positions(text: &str) -> Vec<Position> {
+    let mut positions: Vec<Position> = Vec::new();
+    let mut offset = 0;
+    for (index, line) in text.split('\n').enumerate() {
+        if headline_level(line).is_some() {
+            positions.push(Position {
+                line: index + 1,
+                offset,
+            });
+        }
+        offset += line.len() + 1;
+    }
+    positions
+}
+
+/// Number of stars of a headline line
+pub fn headline_level(line: &str) -> Option<usize> {
+    let stars = line.len() - line.trim_start_matches('*').len();
+    (stars > 0 && line[stars..].starts_with([' ', '\t'])).then_some(stars)
+}
+
+/// The file a headline is in, which is not the converted one for included
+/// headlines, and its position there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub file_path: &'a str,
+    pub position: Position,
+}
+
+/// What is added about the headline to the events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataOptions {
+    /// Add the ancestors' titles (`outlinePath`), the `level`, the `line` and
+    /// byte `offset` of the headline, and its `ID` (`orgId`) to
+    /// `extendedProps`, next to `todo`, `priority` and `tags`
+    pub extended_props: bool,
+    /// Put the ancestors' titles in front of the title words, e.g.
+    /// `SCL: TODO Project X › Review`
+    pub breadcrumb: bool,
+    pub breadcrumb_separator: String,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> MetadataOptions {
+        MetadataOptions {
+            extended_props: false,
+            breadcrumb: false,
+            breadcrumb_separator: " › ".to_string(),
+        }
+    }
+}
+
+/// A headline being converted, with what applies to all of its events
+#[derive(Debug)]
+pub struct HeadlineContext<'a> {
+    /// Borrowed from the document, owned only for the text before the first headline
+    pub title: Cow<'a, elements::Title<'a>>,
+    /// Titles of the ancestors and the headline
+    pub outline_path: Vec<String>,
+    /// Number of the earlier headlines of the file with the same outline path
+    pub duplicate: usize,
+    /// Values of the `FC_` properties
+    pub overrides: Overrides,
+    /// `#+FILETAGS`, the ancestors' tags and the headline's own tags
+    pub tags: Vec<String>,
+    /// `CATEGORY` of the headline or an ancestor, `#+CATEGORY` or the file name
+    pub category: Option<String>,
+    pub file_path: &'a str,
+    /// `file_path` for the ids, resolved once per file
+    pub id_path: Rc<str>,
+    /// Closed CLOCK lines: start and end
+    pub clocks: Vec<(&'a elements::Datetime<'a>, &'a elements::Datetime<'a>)>,
+    /// Timestamps of the title and the section, outside of drawers
+    pub timestamps: Vec<&'a elements::Timestamp<'a>>,
+    /// Days whose occurrence has been cancelled by a note in the LOGBOOK
+    pub cancelled_dates: Vec<NaiveDate>,
+    /// Is the TODO keyword a done state?
+    pub done: bool,
+    /// Color for the TODO keyword, the priority or the category, or the
+    /// grey of done items
+    pub default_color: Option<String>,
+    /// Does `default_color` replace the colors set by properties?
+    pub greyed_out: bool,
+    /// Rank of the priority, 0 for the highest
+    pub priority_rank: Option<u32>,
+    /// Put `[#A] ` in front of the title
+    pub priority_marker: bool,
+    /// Compute the text color from the background
+    pub contrast_text: bool,
+    /// How the title words are rendered
+    pub title_options: TitleOptions,
+    /// The section rendered for the description
+    pub body: Option<String>,
+    pub description_source: DescriptionSource,
+    /// The file's templates, else those of the options
+    pub title_template: Option<Template>,
+    pub description_template: Option<Template>,
+    pub prefixes: PrefixOptions,
+    /// `#+LANGUAGE` of the file
+    pub language: Option<String>,
+    /// Where the headline starts, None when the text is not known
+    pub position: Option<Position>,
+    pub metadata: MetadataOptions,
+    pub editor_links: EditorLinkOptions,
+}
+
+impl<'a> HeadlineContext<'a> {
+    /// Context of the current headline of an outline, whose title gets the
+    /// inherited properties. Problems with its properties are added to `diagnostics`.
+    pub fn new(
+        outline: &Outline<'a>,
+        file_path: &'a str,
+        settings: &FileSettings,
+        options: &ConvertOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> HeadlineContext<'a> {
+        let title = outline
+            .current()
+            .expect("HeadlineContext::new needs a headline");
+        let title: Cow<'a, elements::Title<'a>> =
+            match inherited_properties(outline, settings, &options.inherit_properties) {
+                Some(properties) => Cow::Owned(elements::Title {
+                    properties,
+                    ..title.clone()
+                }),
+                None => Cow::Borrowed(title),
+            };
+        let category = category(outline, settings, file_path);
+        let done = title.keyword.as_deref().is_some_and(|keyword| {
+            settings
+                .todo_keywords
+                .as_ref()
+                .unwrap_or(&options.todo_keywords)
+                .is_done(keyword)
+        });
+        let greyed_out = done && options.done_items == DoneItems::Grey;
+        let default_color = if greyed_out {
+            Some(
+                options
+                    .done_color
+                    .map(|color| color.to_string())
+                    .unwrap_or_else(|| DEFAULT_DONE_COLOR.to_string()),
+            )
+        } else {
+            let keyword_color = title
+                .keyword
+                .as_deref()
+                .and_then(|keyword| options.todo_colors.get(keyword));
+            let priority_color = title
+                .priority
+                .and_then(|priority| options.priority.colors.get(&priority.to_string()));
+            keyword_color
+                .or(priority_color)
+                .map(|color| color.to_string())
+                .or_else(|| options.colors.palette_color(category.as_deref(), file_path))
+        };
+        let priority_rank = if title.priority.is_some() || options.priority.rank_default {
+            Some(settings.priorities.unwrap_or_default().rank(title.priority))
+        } else {
+            None
+        };
+        let priority_marker = options.priority.title_marker && title.priority.is_some();
+        let (overrides, problems) = Overrides::from_title(&title, file_path);
+        diagnostics.extend(problems);
+        let mut tags: Vec<String> = settings.file_tags.clone();
+        for headline in outline.ancestors().iter().chain([&title.as_ref()]) {
+            merge_tags(&mut tags, headline.tags.iter().map(|tag| tag.as_ref()));
+        }
+        let mut context = HeadlineContext {
+            title,
+            outline_path: outline.path(),
+            duplicate: 0,
+            overrides,
+            tags,
+            category,
+            file_path,
+            id_path: Rc::from(file_path),
+            clocks: Vec::new(),
+            timestamps: Vec::new(),
+            cancelled_dates: Vec::new(),
+            done,
+            default_color,
+            greyed_out,
+            priority_rank,
+            priority_marker,
+            contrast_text: options.colors.contrast_text,
+            title_options: options.title.clone(),
+            body: None,
+            description_source: options.description.source,
+            title_template: settings
+                .title_template
+                .clone()
+                .or_else(|| options.templates.title.clone()),
+            description_template: settings
+                .description_template
+                .clone()
+                .or_else(|| options.templates.description.clone()),
+            prefixes: options.prefixes.clone(),
+            language: settings.language.clone(),
+            position: None,
+            metadata: options.metadata.clone(),
+            editor_links: options.editor_links.clone(),
+        };
+        context.drop_count(diagnostics);
+        context
+    }
+
+    /// A series ends after a number of occurrences or on a date, not both
+    /// (RFC 5545): the date of `FC_UNTIL`, of UNTIL in `FC_RRULE` or of
+    /// CLOSED wins, and the count is reported.
+    fn drop_count(&mut self, diagnostics: &mut Vec<Diagnostic>) {
+        let rrule_until = self
+            .overrides
+            .rrule
+            .as_ref()
+            .is_some_and(|rrule| rrule.until.is_some());
+        let end = if self.overrides.until.is_some() {
+            "FC_UNTIL"
+        } else if rrule_until {
+            "UNTIL of FC_RRULE"
+        } else if self.closed_date().is_some() {
+            "CLOSED"
+        } else {
+            return;
+        };
+        let rrule_count = self
+            .overrides
+            .rrule
+            .as_mut()
+            .and_then(|rrule| rrule.count.take());
+        let count = match (self.overrides.count.take(), rrule_count) {
+            (Some(_), _) => "FC_COUNT",
+            (None, Some(_)) => "COUNT of FC_RRULE",
+            (None, None) => return,
+        };
+        diagnostics.push(Diagnostic::new(
+            self.file_path,
+            &self.title.raw,
+            format!("{} is ignored: the series ends at {}", count, end),
+        ));
+    }
+
+    /// Context for the text before the first headline
+    pub fn document(file_path: &'a str, settings: &FileSettings) -> HeadlineContext<'a> {
+        let mut properties = PropertiesMap::new();
+        for (key, value) in &settings.properties {
+            properties
+                .pairs
+                .push((Cow::Owned(key.clone()), Cow::Owned(value.clone())));
+        }
+        HeadlineContext {
+            title: Cow::Owned(elements::Title {
+                priority: None,
+                tags: Vec::new(),
+                keyword: None,
+                raw: Cow::Borrowed(""),
+                planning: None,
+                properties,
+                post_blank: 0,
+                level: 0,
+            }),
+            outline_path: Vec::new(),
+            duplicate: 0,
+            overrides: Overrides::default(),
+            tags: settings.file_tags.clone(),
+            category: category(&Outline::new(), settings, file_path),
+            file_path,
+            id_path: Rc::from(file_path),
+            clocks: Vec::new(),
+            timestamps: Vec::new(),
+            cancelled_dates: Vec::new(),
+            done: false,
+            default_color: None,
+            greyed_out: false,
+            priority_rank: None,
+            priority_marker: false,
+            contrast_text: false,
+            title_options: TitleOptions::default(),
+            body: None,
+            description_source: DescriptionSource::default(),
+            title_template: None,
+            description_template: None,
+            prefixes: PrefixOptions::default(),
+            language: settings.language.clone(),
+            position: None,
+            metadata: MetadataOptions::default(),
+            editor_links: EditorLinkOptions::default(),
+        }
+    }
+
+    /// Prefix of the titles of the events of a kind
+    fn prefix(&self, kind: EventKind) -> String {
+        self.prefixes
+            .prefix(TimestampKind::from(kind), self.language.as_deref())
+    }
+
+    /// Render the title: the prefix, the keyword, the priority marker and
+    /// the title words
+    fn render_title(&self, event: &mut FCEvent, kind: EventKind) {
+        let keyword = match &self.title.keyword {
+            Some(keyword) if kind.shows_keyword() => format!("{} ", keyword),
+            _ => String::new(),
+        };
+        let before = format!("{}{}", self.prefix(kind), keyword);
+        let marker = match self.title.priority {
+            Some(priority) if self.priority_marker => format!("[#{}] ", priority),
+            _ => String::new(),
+        };
+        if self.title_options.markup == TitleMarkup::Html {
+            let title_html = format!(
+                "{}{}{}",
+                escape_html(&before),
+                escape_html(&marker),
+                self.title_words(true)
+            );
+            event
+                .extended_props
+                .insert("titleHtml".to_string(), Value::from(title_html));
+        }
+        event.title = format!("{}{}{}", before, marker, self.title_words(false));
+    }
+
+    /// A title as set by the markup options
+    fn render_words(&self, raw: &str, as_html: bool) -> String {
+        let cookies = self.title_options.cookies;
+        match (self.title_options.markup, as_html) {
+            (TitleMarkup::Raw, true) => escape_html(raw),
+            (TitleMarkup::Raw, false) => raw.to_string(),
+            (_, true) => html(raw, cookies),
+            (_, false) => plain_text(raw, cookies),
+        }
+    }
+
+    /// The title words, after the ancestors' with a breadcrumb title
+    fn title_words(&self, as_html: bool) -> String {
+        if self.metadata.breadcrumb {
+            self.breadcrumb(as_html)
+        } else {
+            self.render_words(&self.title.raw, as_html)
+        }
+    }
+
+    /// Titles of the ancestors and the headline, e.g. `Project X › Review`
+    fn breadcrumb(&self, as_html: bool) -> String {
+        let titles: Vec<String> = self
+            .outline_path
+            .iter()
+            .map(|raw| self.render_words(raw, as_html))
+            .collect();
+        let separator = &self.metadata.breadcrumb_separator;
+        if as_html {
+            titles.join(&escape_html(separator))
+        } else {
+            titles.join(separator)
+        }
+    }
+
+    /// Titles of the ancestors
+    fn ancestors(&self) -> &[String] {
+        self.outline_path
+            .split_last()
+            .map_or(&[], |(_, ancestors)| ancestors)
+    }
+
+    /// The outline path, level, position and ID of the headline
+    fn add_metadata(&self, event: &mut FCEvent) {
+        let outline_path: Vec<String> = self
+            .ancestors()
+            .iter()
+            .map(|raw| self.render_words(raw, false))
+            .collect();
+        let props = &mut event.extended_props;
+        props.insert("outlinePath".to_string(), Value::from(outline_path));
+        props.insert("level".to_string(), Value::from(self.title.level));
+        if let Some(position) = self.position {
+            props.insert("line".to_string(), Value::from(position.line));
+            props.insert("offset".to_string(), Value::from(position.offset));
+        }
+        if let Some(id) = self.id() {
+            props.insert("orgId".to_string(), Value::from(id));
+        }
+    }
+
+    /// `ID` property of the headline
+    fn id(&self) -> Option<&str> {
+        self.title
+            .properties
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("ID"))
+            .map(|(_, value)| value.trim())
+            .last()
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Value of a template field for an event, escaped for HTML or not
+    fn field(&self, name: &str, event: &FCEvent, kind: EventKind, as_html: bool) -> Option<String> {
+        let source = if as_html {
+            DescriptionSource::Html
+        } else {
+            DescriptionSource::Plain
+        };
+        let value = match name {
+            "title" => return Some(self.render_words(&self.title.raw, as_html)),
+            "breadcrumb" => return Some(self.breadcrumb(as_html)),
+            "body" => return self.body.clone(),
+            "description" => {
+                let properties = self.title.properties.iter();
+                return property_description(
+                    properties.map(|(key, value)| (key.to_string(), value.to_string())),
+                    source,
+                );
+            }
+            "prefix" => self.prefix(kind),
+            "keyword" => self.title.keyword.as_ref()?.to_string(),
+            "priority" => self.title.priority?.to_string(),
+            "tags" => self.tags.join(", "),
+            "outline_path" => self.outline_path.join(" / "),
+            "file" => Path::new(self.file_path)
+                .file_name()?
+                .to_string_lossy()
+                .to_string(),
+            "category" => self.category.clone()?,
+            "start" => match event.start {
+                FCStart::Day(date) => date.format("%Y-%m-%d").to_string(),
+                FCStart::DateTime(datetime) => format_naive_datetime(datetime),
+            },
+            "end" => format_naive_datetime(event.end?),
+            "start_time" => match event.start {
+                FCStart::DateTime(datetime) => datetime.format("%H:%M").to_string(),
+                FCStart::Day(..) => return None,
+            },
+            "end_time" => event.end?.format("%H:%M").to_string(),
+            "duration" => {
+                let minutes = (event.end? - event.start.to_naive_date_time()).num_minutes();
+                format!("{}:{:02}", minutes / 60, minutes % 60)
+            }
+            property => self
+                .title
+                .properties
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case(property))
+                .map(|(_, value)| value.trim().to_string())
+                .last()?,
+        };
+        Some(match source {
+            DescriptionSource::Html => escape_html(&value),
+            _ => value,
+        })
+    }
+
+    /// Finish an event made from the headline: apply the `FC_` properties,
+    /// add the TODO state and the priority, render the title and the body,
+    /// add the metadata, the kind, the tags and the category, fill in the
+    /// colors, end or thin out a repeating event, and give it its stable id
+    /// unless `FC_ID` has already set one.
+    pub fn finish(&self, mut event: FCEvent, kind: EventKind) -> FCEvent {
+        self.overrides.apply(&mut event);
+        self.add_state(&mut event);
+        if self.overrides.title.is_none() {
+            self.apply_title(&mut event, kind);
+        }
+        if self.title_options.link_url && event.url.is_none() {
+            event.url = first_link(&self.title.raw);
+        }
+        self.apply_description(&mut event, kind);
+        if self.metadata.extended_props {
+            self.add_metadata(&mut event);
+        }
+        self.add_links_and_classes(&mut event, kind);
+        self.apply_colors(&mut event);
+        self.apply_recurrence(&mut event);
+        if event.id.is_none() {
+            event.id = Some(make_id(
+                &self.title,
+                &self.outline_path,
+                self.duplicate,
+                kind,
+                &event.start,
+                &self.id_path,
+            ));
+        }
+        event
+    }
+
+    /// The TODO keyword, whether it is done, and the priority
+    fn add_state(&self, event: &mut FCEvent) {
+        let props = &mut event.extended_props;
+        if let Some(keyword) = &self.title.keyword {
+            props.insert("todo".to_string(), Value::from(keyword.as_ref()));
+            props.insert("done".to_string(), Value::from(self.done));
+        }
+        if let Some(priority) = self.title.priority {
+            props.insert("priority".to_string(), Value::from(priority.to_string()));
+        }
+    }
+
+    /// The title, from the template or else from the headline
+    fn apply_title(&self, event: &mut FCEvent, kind: EventKind) {
+        let template = match &self.title_template {
+            Some(template) => template,
+            None => return self.render_title(event, kind),
+        };
+        if self.title_options.markup == TitleMarkup::Html {
+            let title_html = template.render(|field| self.field(field, event, kind, true));
+            event
+                .extended_props
+                .insert("titleHtml".to_string(), Value::from(title_html));
+        }
+        event.title = template.render(|field| self.field(field, event, kind, false));
+    }
+
+    /// The description, from the template, the body or the `DESCRIPTION`
+    /// properties
+    fn apply_description(&self, event: &mut FCEvent, kind: EventKind) {
+        if let Some(template) = &self.description_template {
+            let as_html = self.description_source != DescriptionSource::Plain;
+            let description = template.render(|field| self.field(field, event, kind, as_html));
+            event.description = Some(description).filter(|text| !text.trim().is_empty());
+        } else if self.description_source != DescriptionSource::Property {
+            event.description = self.body.clone().or_else(|| {
+                let properties = self.title.properties.iter();
+                property_description(
+                    properties.map(|(key, value)| (key.to_string(), value.to_string())),
+                    self.description_source,
+                )
+            });
+        }
+    }
+
+    /// The editor link, and the kind, priority rank, done state, category
+    /// and tags as properties and class names
+    fn add_links_and_classes(&self, event: &mut FCEvent, kind: EventKind) {
+        let editor_link = self
+            .editor_links
+            .link(self.file_path, self.position, self.id());
+        if let Some(link) = editor_link {
+            if event.url.is_none() || self.editor_links.replace_url {
+                event.url = Some(link.clone());
+            }
+            event
+                .extended_props
+                .insert("editorUrl".to_string(), Value::from(link));
+        }
+        if self.prefixes.kind_props() {
+            let kind = TimestampKind::from(kind).name();
+            event
+                .extended_props
+                .insert("kind".to_string(), Value::from(kind));
+            let class_name = format!("{}{}", KIND_CLASS_PREFIX, kind);
+            if !event.class_names.contains(&class_name) {
+                event.class_names.push(class_name);
+            }
+        }
+        if let Some(rank) = self.priority_rank {
+            event
+                .extended_props
+                .insert("priorityRank".to_string(), Value::from(rank));
+        }
+        if self.done && !event.class_names.iter().any(|name| name == "done") {
+            event.class_names.push("done".to_string());
+        }
+        if let Some(category) = &self.category {
+            event
+                .extended_props
+                .insert("category".to_string(), Value::from(category.as_str()));
+        }
+        if !self.tags.is_empty() {
+            for class_name in self.tags.iter().map(|tag| tag_class_name(tag)) {
+                if !event.class_names.contains(&class_name) {
+                    event.class_names.push(class_name);
+                }
+            }
+            event
+                .extended_props
+                .insert("tags".to_string(), Value::from(self.tags.clone()));
+        }
+    }
+
+    /// The default color, grey for greyed out events, and a text color
+    /// which stands out from the background
+    fn apply_colors(&self, event: &mut FCEvent) {
+        if self.greyed_out {
+            event.color = self.default_color.clone();
+            event.background_color = None;
+            event.border_color = None;
+        } else if event.color.is_none() && event.background_color.is_none() {
+            event.color = self.default_color.clone();
+        }
+        if self.contrast_text && event.text_color.is_none() {
+            let background = event.background_color.as_ref().or(event.color.as_ref());
+            event.text_color = background.and_then(|color| contrast_text_color(color));
+        }
+    }
+
+    /// Skip the `FC_EXDATE` and cancelled dates of a repeating event, and
+    /// end it after `FC_COUNT` occurrences or at the earliest end date
+    fn apply_recurrence(&self, event: &mut FCEvent) {
+        let rrule = match event.rrule.as_mut() {
+            Some(rrule) => rrule,
+            None => return,
+        };
+        let dtstart = rrule.dtstart;
+        let skipped = self
+            .overrides
+            .exdate
+            .iter()
+            .copied()
+            .chain(self.cancelled_dates.iter().map(|date| FCStart::Day(*date)));
+        for date in skipped {
+            let exdate = align_exdate(date, &dtstart);
+            if !event.exdate.contains(&exdate) {
+                event.exdate.push(exdate);
+            }
+        }
+        if let Some(count) = self.overrides.count {
+            rrule.count = Some(count);
+        }
+        let untils = [
+            self.overrides
+                .until
+                .map(|date| until_end_of(date, &dtstart)),
+            self.closed_until(&dtstart),
+        ];
+        for until in untils.into_iter().flatten() {
+            rrule.until = Some(match rrule.until {
+                Some(earlier) if earlier < until => earlier,
+                _ => until,
+            });
+        }
+        // An RRULE has COUNT or UNTIL, not both: the end date wins
+        if rrule.until.is_some() {
+            rrule.count = None;
+        }
+    }
+
+    /// A done headline ends its series when it was closed
+    fn closed_until(&self, dtstart: &FCStart) -> Option<FCStart> {
+        let closed = self.closed_date()?;
+        Some(match dtstart {
+            FCStart::Day(..) => FCStart::Day(closed.into()),
+            FCStart::DateTime(..) => FCStart::DateTime(closed.into()),
+        })
+    }
+
+    /// CLOSED date of a done headline
+    fn closed_date(&self) -> Option<&elements::Datetime<'a>> {
+        if !self.done {
+            return None;
+        }
+        match &self.title.planning.as_ref()?.closed {
+            Some(elements::Timestamp::Inactive { start, .. })
+            | Some(elements::Timestamp::Active { start, .. }) => Some(start),
+            _ => None,
+        }
+    }
+}
+
+/// Walk an Org document and collect its headlines with their CLOCK lines,
+/// timestamps, LOGBOOK notes and tags. The first entry is the text before the first
+/// headline. `positions` are those of [`headline_positions`], or empty when
+/// the text is not known. Problems with properties are added to `diagnostics`.
+pub fn collect_headlines<'a>(
+    org: &'a Org,
+    locations: &[Location<'a>],
+    file_path: &'a str,
+    settings: &FileSettings,
+    options: &ConvertOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<HeadlineContext<'a>> {
+    let mut headlines: Vec<HeadlineContext> = vec![HeadlineContext::document(file_path, settings)];
+    // Resolved once per file rather than per event
+    let mut id_paths: HashMap<&str, Rc<str>> = HashMap::new();
+    let mut resolve = |path: &'a str| -> Rc<str> {
+        id_paths
+            .entry(path)
+            .or_insert_with(|| Rc::from(id_path(path)))
+            .clone()
+    };
+    headlines[0].id_path = resolve(file_path);
+    let mut outline = Outline::new();
+    let mut in_drawer = false;
+    let mut in_logbook = false;
+    let mut note: Option<LogbookNote> = None;
+    let mut body: Option<BodyWriter> = None;
+    let mut outline_paths: HashMap<(&str, Vec<String>), usize> = HashMap::new();
+    // A template may show the body even when the descriptions are not made from it
+    let description_template = settings
+        .description_template
+        .as_ref()
+        .or(options.templates.description.as_ref());
+    let body_options = match options.description.source {
+        DescriptionSource::Property if description_template.is_some_and(|t| t.uses("body")) => {
+            DescriptionOptions {
+                source: DescriptionSource::Html,
+                ..options.description.clone()
+            }
+        }
+        _ => options.description.clone(),
+    };
+
+    for event in org.iter() {
+        match &event {
+            Event::Start(elements::Element::Section) => {
+                body = BodyWriter::new(&body_options);
+            }
+            Event::End(elements::Element::Section) => {
+                if let (Some(writer), Some(headline)) = (body.take(), headlines.last_mut()) {
+                    headline.body = writer.finish();
+                }
+            }
+            Event::Start(element) => body.iter_mut().for_each(|writer| writer.start(element)),
+            Event::End(element) => body.iter_mut().for_each(|writer| writer.end(element)),
+        }
+        match event {
+            Event::Start(elements::Element::Title(title)) => {
+                outline.enter(title);
+                let location = locations.get(headlines.len() - 1);
+                let headline_path = location.map_or(file_path, |location| location.file_path);
+                let mut headline =
+                    HeadlineContext::new(&outline, headline_path, settings, options, diagnostics);
+                headline.position = location.map(|location| location.position);
+                headline.id_path = resolve(headline_path);
+                let seen = outline_paths
+                    .entry((headline_path, headline.outline_path.clone()))
+                    .or_default();
+                headline.duplicate = *seen;
+                *seen += 1;
+                headlines.push(headline);
+            }
+            Event::Start(elements::Element::Drawer(drawer)) => {
+                in_drawer = true;
+                in_logbook = drawer.name.eq_ignore_ascii_case("LOGBOOK");
+            }
+            Event::End(elements::Element::Drawer(..)) => {
+                in_drawer = false;
+                in_logbook = false;
+            }
+            Event::Start(elements::Element::Clock(elements::Clock::Closed {
+                start, end, ..
+            })) => {
+                if let Some(headline) = headlines.last_mut() {
+                    headline.clocks.push((start, end));
+                }
+            }
+            Event::Start(elements::Element::ListItem(..)) if in_logbook => {
+                note = Some(LogbookNote::default());
+            }
+            Event::Start(elements::Element::Text { value }) => {
+                if let Some(note) = note.as_mut() {
+                    note.text.push_str(value);
+                }
+            }
+            Event::Start(elements::Element::Timestamp(timestamp)) => {
+                if let Some(note) = note.as_mut() {
+                    note.add_timestamp(timestamp);
+                } else if let (false, Some(headline)) = (in_drawer, headlines.last_mut()) {
+                    headline.timestamps.push(timestamp);
+                }
+            }
+            Event::End(elements::Element::ListItem(..)) => {
+                let cancelled = note.take().and_then(|note| note.cancelled_date());
+                if let (Some(date), Some(headline)) = (cancelled, headlines.last_mut()) {
+                    headline.cancelled_dates.push(date);
+                }
+            }
+            _ => {}
+        }
+    }
+    headlines
+}
+
+/// A list item in a LOGBOOK drawer, e.g.
+/// `- State "CANCELLED"  from "TODO"  <2022-07-27 Wed 17:00>`
+#[derive(Debug, Default)]
+struct LogbookNote {
+    text: String,
+    /// Day of the first active timestamp: the occurrence the note is about
+    active: Option<NaiveDate>,
+}
+
+impl LogbookNote {
+    fn add_timestamp(&mut self, timestamp: &elements::Timestamp) {
+        if let elements::Timestamp::Active { start, .. }
+        | elements::Timestamp::ActiveRange { start, .. } = timestamp
+        {
+            self.active = self.active.or_else(|| Some(start.into()));
+        }
+    }
+
+    /// The cancelled occurrence, if the note records a change to a cancelled
+    /// state. The inactive timestamp is when the note was written, not the
+    /// occurrence, so a note without an active one cancels nothing.
+    fn cancelled_date(&self) -> Option<NaiveDate> {
+        let text = self.text.trim_start();
+        let cancelled = CANCELLED_KEYWORDS
+            .iter()
+            .any(|keyword| text.starts_with(&format!("State \"{}\"", keyword)));
+        if !cancelled {
+            return None;
+        }
+        self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_headline_positions() {
+        let text = "#+TITLE: x\n* One\n*bold* text\n**\tTwo\n***\n";
+        assert_eq!(
+            headline_positions(text),
+            [
+                Position {
+                    line: 2,
+                    offset: 11
+                },
+                Position {
+                    line: 4,
+                    offset: 29
+                },
+            ]
+        );
+    }
+
+    fn july(day: u8) -> elements::Datetime<'static> {
+        elements::Datetime {
+            year: 2022,
+            month: 7,
+            day,
+            dayname: Cow::Borrowed(""),
+            hour: Some(17),
+            minute: Some(0),
+        }
+    }
+
+    #[test]
+    fn cancel_the_occurrence_of_a_note() {
+        let mut note = LogbookNote {
+            text: "State \"CANCELLED\"  from \"TODO\"  ".to_string(),
+            ..LogbookNote::default()
+        };
+        // Written on the 30th, about no occurrence in particular
+        note.add_timestamp(&elements::Timestamp::Inactive {
+            start: july(30),
+            repeater: None,
+            delay: None,
+        });
+        assert_eq!(note.cancelled_date(), None);
+        note.add_timestamp(&elements::Timestamp::Active {
+            start: july(27),
+            repeater: None,
+            delay: None,
+        });
+        assert_eq!(note.cancelled_date(), NaiveDate::from_ymd_opt(2022, 7, 27));
+    }
+}
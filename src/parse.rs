@@ -1,19 +1,32 @@
+use crate::agenda::AgendaFiles;
+use crate::config::{ClockEvents, Config};
+use crate::diagnostic::print_diagnostics;
+use crate::diagnostic::Diagnostic;
 use crate::fullcalendar::make_event_from_clocks;
+use crate::fullcalendar::make_event_from_closed;
 use crate::fullcalendar::make_event_from_deadline_with_range;
 use crate::fullcalendar::make_event_from_deadline_wo_range;
 use crate::fullcalendar::make_event_from_scheduled_with_range;
 use crate::fullcalendar::make_event_from_scheduled_wo_range;
+use crate::fullcalendar::make_event_from_timestamp;
+use crate::fullcalendar::EventKind;
 use crate::fullcalendar::FCEvent;
+use crate::include::{expand_includes, Expanded};
+use crate::options::ConvertOptions;
+use crate::options::DoneItems;
+use crate::outline::HeadlineContext;
+use crate::outline::{collect_headlines, headline_positions, Location};
+use crate::rrule::expand_recurring_events;
+use crate::scan::{scan, ScanOptions};
+use crate::settings::FileSettings;
 use crate::timestamp::date_is_n_days_before_or_after_now;
-use glob::glob;
+use crate::todo::parse_org;
+use crate::walk::{org_files, WalkOptions};
+use chrono::Local;
 use orgize::elements;
-use orgize::elements::PropertiesMap;
-use orgize::Event;
 use orgize::Org;
-use std::borrow::Cow;
 use std::error::Error;
-use std::fs::File;
-use std::io::Read;
+use std::io::Write;
 use std::path::Path;
 
 /// Pase Org mode text and output a vector of FCEvent
@@ -23,165 +36,241 @@ pub fn parsed_org_to_fc_vec(
     ignore_after_days: i64,
     file_path: &str,
 ) -> Vec<FCEvent> {
-    let mut fullcalendar_events: Vec<FCEvent> = Vec::new();
-    for event in org.iter() {
-        // println!("{:?}", event);
-        match event {
-            Event::Start(element) => match element {
-                elements::Element::Title(title) => match &title.planning {
-                    Some(plan) => {
-                        match &plan.deadline {
-                            Some(deadline) => match deadline {
-                                elements::Timestamp::Active {
-                                    start, repeater, ..
-                                } => {
-                                    push_deadline_wo_range(
-                                        &mut fullcalendar_events,
-                                        &title,
-                                        &start,
-                                        &repeater,
-                                        ignore_before_days,
-                                        ignore_after_days,
-                                        &file_path,
-                                    );
-                                }
-                                elements::Timestamp::ActiveRange {
-                                    start,
-                                    end,
-                                    start_repeater,
-                                    ..
-                                } => {
-                                    if date_is_n_days_before_or_after_now(
-                                        start,
-                                        ignore_before_days,
-                                        ignore_after_days,
-                                    ) {
-                                        fullcalendar_events.push(
-                                            make_event_from_deadline_with_range(
-                                                &title,
-                                                &start,
-                                                &end,
-                                                &start_repeater,
-                                                &file_path,
-                                            ),
-                                        );
-                                    }
-                                }
-
-                                _ => {}
-                            },
-                            None => {}
-                        }
-                        match &plan.scheduled {
-                            Some(schedule) => match schedule {
-                                elements::Timestamp::Active {
-                                    start, repeater, ..
-                                } => {
-                                    if date_is_n_days_before_or_after_now(
-                                        start,
-                                        ignore_before_days,
-                                        ignore_after_days,
-                                    ) {
-                                        fullcalendar_events.push(
-                                            make_event_from_scheduled_wo_range(
-                                                &title, &start, &repeater, &file_path,
-                                            ),
-                                        );
-                                    }
-                                }
-                                elements::Timestamp::ActiveRange {
-                                    start,
-                                    end,
-                                    start_repeater,
-                                    ..
-                                } => {
-                                    if date_is_n_days_before_or_after_now(
-                                        start,
-                                        ignore_before_days,
-                                        ignore_after_days,
-                                    ) {
-                                        fullcalendar_events.push(
-                                            make_event_from_scheduled_with_range(
-                                                &title,
-                                                &start,
-                                                &end,
-                                                &start_repeater,
-                                                &file_path,
-                                            ),
-                                        );
-                                    }
-                                }
-
-                                _ => {}
-                            },
-                            None => {}
-                        }
-                    }
-                    None => {}
-                },
-
-                _ => {}
-            },
-            Event::End(element) => match element {
-                elements::Element::Headline { .. } => {}
-                _ => {}
-            },
-        }
-    }
+    let (fullcalendar_events, diagnostics) = parsed_org_to_fc_vec_with_diagnostics(
+        org,
+        ignore_before_days,
+        ignore_after_days,
+        file_path,
+    );
+    print_diagnostics(&diagnostics);
     fullcalendar_events
 }
 
-/// Pase Org mode text and output a vector of FCEvent onfly for clock
-pub fn parsed_org_to_fc_vec_clock(
+/// Same as `parsed_org_to_fc_vec`, but return the problems found instead of printing them
+pub fn parsed_org_to_fc_vec_with_diagnostics(
     org: Org,
     ignore_before_days: i64,
     ignore_after_days: i64,
     file_path: &str,
-) -> Vec<FCEvent> {
-    let mut fullcalendar_events: Vec<FCEvent> = Vec::new();
-
-    let mut title: &elements::Title = &elements::Title {
-        priority: None,
-        tags: Vec::new(),
-        keyword: None,
-        raw: Cow::Borrowed(""),
-        planning: None,
-        properties: PropertiesMap::new(),
-        post_blank: 0,
-        level: 1,
-    };
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    parsed_org_to_fc_vec_with_options(
+        org,
+        file_path,
+        &ConvertOptions::new(ignore_before_days, ignore_after_days),
+    )
+}
 
-    for event in org.iter() {
-        // println!("{:?}", event);
+/// Pase Org mode text and output a vector of FCEvent and the problems found
+pub fn parsed_org_to_fc_vec_with_options(
+    org: Org,
+    file_path: &str,
+    options: &ConvertOptions,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    events(&org, &[], file_path, options)
+}
 
-        match event {
-            Event::Start(element) => match element {
-                elements::Element::Title(title_orig) => {
-                    title = title_orig;
+/// The events of the parsed text, with the locations of its headlines when
+/// the text is known
+fn events(
+    org: &Org,
+    locations: &[Location],
+    file_path: &str,
+    options: &ConvertOptions,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    let ignore_before_days = options.ignore_before_days;
+    let ignore_after_days = options.ignore_after_days;
+    let mut fullcalendar_events: Vec<FCEvent> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let settings = FileSettings::from_org(org);
+    for headline in collect_headlines(
+        org,
+        locations,
+        file_path,
+        &settings,
+        options,
+        &mut diagnostics,
+    ) {
+        if headline.overrides.hide
+            || (headline.done && options.done_items == DoneItems::Hide)
+            || !options.accepts(&headline, &settings)
+        {
+            continue;
+        }
+        let title = headline.title.as_ref();
+        if let Some(plan) = &title.planning {
+            match &plan.deadline {
+                Some(elements::Timestamp::Active {
+                    start, repeater, ..
+                }) => {
+                    push_deadline_wo_range(
+                        &mut fullcalendar_events,
+                        &headline,
+                        start,
+                        repeater,
+                        ignore_before_days,
+                        ignore_after_days,
+                    );
+                }
+                Some(elements::Timestamp::ActiveRange {
+                    start,
+                    end,
+                    start_repeater,
+                    ..
+                }) => {
+                    if date_is_n_days_before_or_after_now(
+                        start,
+                        ignore_before_days,
+                        ignore_after_days,
+                    ) {
+                        fullcalendar_events.push(headline.finish(
+                            make_event_from_deadline_with_range(
+                                title,
+                                start,
+                                end,
+                                start_repeater,
+                                headline.file_path,
+                            ),
+                            EventKind::Deadline,
+                        ));
+                    }
                 }
                 _ => {}
-            },
-            _ => {}
-        }
-
-        if let Event::Start(element) = event {
-            if let elements::Element::Clock(clock) = element {
-                if let elements::Clock::Closed { start, end, .. } = clock {
+            }
+            match &plan.scheduled {
+                Some(elements::Timestamp::Active {
+                    start, repeater, ..
+                }) => {
+                    if date_is_n_days_before_or_after_now(
+                        start,
+                        ignore_before_days,
+                        ignore_after_days,
+                    ) {
+                        fullcalendar_events.push(headline.finish(
+                            make_event_from_scheduled_wo_range(
+                                title,
+                                start,
+                                repeater,
+                                headline.file_path,
+                            ),
+                            EventKind::Scheduled,
+                        ));
+                    }
+                }
+                Some(elements::Timestamp::ActiveRange {
+                    start,
+                    end,
+                    start_repeater,
+                    ..
+                }) => {
                     if date_is_n_days_before_or_after_now(
                         start,
                         ignore_before_days,
                         ignore_after_days,
                     ) {
-                        fullcalendar_events
-                            .push(make_event_from_clocks(&title, &start, &end, &file_path));
+                        fullcalendar_events.push(headline.finish(
+                            make_event_from_scheduled_with_range(
+                                title,
+                                start,
+                                end,
+                                start_repeater,
+                                headline.file_path,
+                            ),
+                            EventKind::Scheduled,
+                        ));
                     }
                 }
+                _ => {}
             }
         }
+        push_timestamp_events(&mut fullcalendar_events, &headline, options);
+    }
+    if let Some(expand) = &options.expand_recurring {
+        let (from, to) = expand.window(Local::now().date_naive());
+        let (expanded, problems) = expand_recurring_events(fullcalendar_events, from, to);
+        fullcalendar_events = expanded;
+        diagnostics.extend(problems);
     }
+    (fullcalendar_events, diagnostics)
+}
+
+/// Pase Org mode text and output a vector of FCEvent onfly for clock
+pub fn parsed_org_to_fc_vec_clock(
+    org: Org,
+    ignore_before_days: i64,
+    ignore_after_days: i64,
+    file_path: &str,
+) -> Vec<FCEvent> {
+    let (fullcalendar_events, diagnostics) = parsed_org_to_fc_vec_clock_with_diagnostics(
+        org,
+        ignore_before_days,
+        ignore_after_days,
+        file_path,
+    );
+    print_diagnostics(&diagnostics);
     fullcalendar_events
 }
 
+/// Same as `parsed_org_to_fc_vec_clock`, but return the problems found instead of printing them
+pub fn parsed_org_to_fc_vec_clock_with_diagnostics(
+    org: Org,
+    ignore_before_days: i64,
+    ignore_after_days: i64,
+    file_path: &str,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    parsed_org_to_fc_vec_clock_with_options(
+        org,
+        file_path,
+        &ConvertOptions::new(ignore_before_days, ignore_after_days),
+    )
+}
+
+/// Pase Org mode text and output a vector of FCEvent for clock and the problems found
+pub fn parsed_org_to_fc_vec_clock_with_options(
+    org: Org,
+    file_path: &str,
+    options: &ConvertOptions,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    clock_events(&org, &[], file_path, options)
+}
+
+/// The events of the parsed text, with the locations of its headlines when
+/// the text is known
+fn clock_events(
+    org: &Org,
+    locations: &[Location],
+    file_path: &str,
+    options: &ConvertOptions,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    let ignore_before_days = options.ignore_before_days;
+    let ignore_after_days = options.ignore_after_days;
+    let mut fullcalendar_events: Vec<FCEvent> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let settings = FileSettings::from_org(org);
+
+    // CLOCK lines before the first headline belong to the document
+    for headline in collect_headlines(
+        org,
+        locations,
+        file_path,
+        &settings,
+        options,
+        &mut diagnostics,
+    ) {
+        if headline.overrides.hide || !options.accepts(&headline, &settings) {
+            continue;
+        }
+        for (clock_index, (start, end)) in headline.clocks.iter().enumerate() {
+            if date_is_n_days_before_or_after_now(start, ignore_before_days, ignore_after_days) {
+                fullcalendar_events.push(headline.finish(
+                    make_event_from_clocks(&headline.title, start, end, headline.file_path),
+                    EventKind::Clock(clock_index),
+                ));
+            }
+        }
+    }
+    (fullcalendar_events, diagnostics)
+}
+
 /// Parse the Org mode content and output FullCalendar JSON file
 pub fn org_to_fc_json(
     contents: &str,
@@ -189,9 +278,10 @@ pub fn org_to_fc_json(
     ignore_after_days: i64,
     file_path: &str,
 ) -> String {
-    let org = Org::parse(&contents);
-    let fullcalendar_events =
-        parsed_org_to_fc_vec(org, ignore_before_days, ignore_after_days, &file_path);
+    let options = ConvertOptions::new(ignore_before_days, ignore_after_days);
+    let (fullcalendar_events, diagnostics) =
+        org_to_fc_vec_with_options(contents, file_path, &options);
+    print_diagnostics(&diagnostics);
     serde_json::to_string_pretty(&fullcalendar_events).unwrap()
 }
 
@@ -202,92 +292,277 @@ pub fn org_to_fc_clock_json(
     ignore_after_days: i64,
     file_path: &str,
 ) -> String {
-    let org = Org::parse(&contents);
-    let fullcalendar_events =
-        parsed_org_to_fc_vec_clock(org, ignore_before_days, ignore_after_days, &file_path);
+    let options = ConvertOptions::new(ignore_before_days, ignore_after_days);
+    let (fullcalendar_events, diagnostics) =
+        org_to_fc_clock_vec_with_options(contents, file_path, &options);
+    print_diagnostics(&diagnostics);
     serde_json::to_string_pretty(&fullcalendar_events).unwrap()
 }
 
+/// Parse the Org mode content with its TODO keywords and output a vector of FCEvent
+pub fn org_to_fc_vec_with_options(
+    contents: &str,
+    file_path: &str,
+    options: &ConvertOptions,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    let locations = text_locations(contents, file_path);
+    events(
+        &parse_org(contents, options),
+        &locations,
+        file_path,
+        options,
+    )
+}
+
+/// Parse the Org mode content with its TODO keywords and output a vector of FCEvent for clock
+pub fn org_to_fc_clock_vec_with_options(
+    contents: &str,
+    file_path: &str,
+    options: &ConvertOptions,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    let locations = text_locations(contents, file_path);
+    clock_events(
+        &parse_org(contents, options),
+        &locations,
+        file_path,
+        options,
+    )
+}
+
+/// The locations of the headlines of a text, all in the same file
+fn text_locations<'a>(contents: &str, file_path: &'a str) -> Vec<Location<'a>> {
+    headline_positions(contents)
+        .into_iter()
+        .map(|position| Location {
+            file_path,
+            position,
+        })
+        .collect()
+}
+
+/// Read FullCalendar JSON (e.g. a previous output) back into a vector of FCEvent
+pub fn fc_json_to_vec(json: &str) -> std::result::Result<Vec<FCEvent>, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
 pub fn push_deadline_wo_range(
     events: &mut Vec<FCEvent>,
-    title: &elements::Title,
+    headline: &HeadlineContext,
     start: &elements::Datetime,
     repeater: &Option<elements::Repeater>,
     ignore_before_days: i64,
     ignore_after_days: i64,
-    file_path: &str,
 ) {
     if date_is_n_days_before_or_after_now(start, ignore_before_days, ignore_after_days) {
-        events.push(make_event_from_deadline_wo_range(
-            &title, &start, &repeater, &file_path,
+        events.push(headline.finish(
+            make_event_from_deadline_wo_range(&headline.title, start, repeater, headline.file_path),
+            EventKind::Deadline,
         ));
     }
 }
 
+/// Push the events of the CLOSED line and of the timestamps in the text
+/// of a headline, of the kinds turned on in the options
+fn push_timestamp_events(
+    events: &mut Vec<FCEvent>,
+    headline: &HeadlineContext,
+    options: &ConvertOptions,
+) {
+    let kinds = options.timestamp_events;
+    let in_window = |start: &elements::Datetime| {
+        date_is_n_days_before_or_after_now(
+            start,
+            options.ignore_before_days,
+            options.ignore_after_days,
+        )
+    };
+    let title = headline.title.as_ref();
+    let closed = title.planning.as_ref().and_then(|plan| match &plan.closed {
+        Some(elements::Timestamp::Inactive { start, .. })
+        | Some(elements::Timestamp::Active { start, .. }) => Some(start),
+        _ => None,
+    });
+    if let Some(closed) = closed.filter(|closed| kinds.closed && in_window(closed)) {
+        events.push(headline.finish(
+            make_event_from_closed(title, closed, headline.file_path),
+            EventKind::Closed,
+        ));
+    }
+
+    // Numbered apart, so that the ids of the kinds turned on do not change
+    let (mut active, mut inactive) = (0, 0);
+    for timestamp in &headline.timestamps {
+        let (start, end, repeater, kind) = match timestamp {
+            elements::Timestamp::Active {
+                start, repeater, ..
+            } => (start, None, repeater, EventKind::Timestamp(active)),
+            elements::Timestamp::ActiveRange {
+                start,
+                end,
+                start_repeater,
+                ..
+            } => (
+                start,
+                Some(end),
+                start_repeater,
+                EventKind::Timestamp(active),
+            ),
+            elements::Timestamp::Inactive {
+                start, repeater, ..
+            } => (start, None, repeater, EventKind::Inactive(inactive)),
+            elements::Timestamp::InactiveRange {
+                start,
+                end,
+                start_repeater,
+                ..
+            } => (
+                start,
+                Some(end),
+                start_repeater,
+                EventKind::Inactive(inactive),
+            ),
+            elements::Timestamp::Diary { .. } => continue,
+        };
+        let wanted = match kind {
+            EventKind::Timestamp(..) => {
+                active += 1;
+                kinds.active
+            }
+            _ => {
+                inactive += 1;
+                kinds.inactive
+            }
+        };
+        if wanted && in_window(start) {
+            events.push(headline.finish(
+                make_event_from_timestamp(title, start, end, repeater, kind, headline.file_path),
+                kind,
+            ));
+        }
+    }
+}
+
 /// Make a FC Event vector from one Org file
 pub fn orgfile_to_fc_json_vec(
     file: &str,
     ignore_before_days: i64,
     ignore_after_days: i64,
 ) -> std::result::Result<Vec<FCEvent>, Box<dyn Error>> {
-    let file_path = Path::new(file).canonicalize();
-
-    let file_path = match file_path {
-        Ok(path) => path,
-        Err(e) => return Err(Box::new(e)),
-    };
+    let options = ConvertOptions::new(ignore_before_days, ignore_after_days);
+    let (fullcalendar_events, diagnostics) =
+        orgfile_to_fc_vec_with_options(file, &options, ClockEvents::Exclude)?;
+    print_diagnostics(&diagnostics);
+    Ok(fullcalendar_events)
+}
 
-    //    println!("PATH: {}", file_path.to_str().unwrap());
-    let f = File::open(file_path);
-    let mut f = match f {
-        Ok(file) => file,
-        Err(e) => return Err(Box::new(e)),
-    };
+/// Make a FC Event vector from one Org file, with its included and setup
+/// files, and the problems found
+pub fn orgfile_to_fc_vec_with_options(
+    file_path: &str,
+    options: &ConvertOptions,
+    clocks: ClockEvents,
+) -> std::result::Result<(Vec<FCEvent>, Vec<Diagnostic>), Box<dyn Error>> {
+    let expanded = expand_includes(file_path)?;
+    Ok(expanded_to_fc_vec(&expanded, file_path, options, clocks))
+}
 
-    let mut s = String::new();
+/// Make a FC Event vector from the text of an Org file with its included
+/// files, parsed once for both kinds of events
+pub fn expanded_to_fc_vec(
+    expanded: &Expanded,
+    file_path: &str,
+    options: &ConvertOptions,
+    clocks: ClockEvents,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    let locations: Vec<Location> = headline_positions(&expanded.text)
+        .into_iter()
+        .map(|position| {
+            let (file_path, position) = expanded.locate(position);
+            Location {
+                file_path,
+                position,
+            }
+        })
+        .collect();
+    let org = parse_org(&expanded.text, options);
 
-    match f.read_to_string(&mut s) {
-        Ok(_) => {}
-        Err(e) => return Err(Box::new(e)),
+    let (mut fullcalendar_events, mut diagnostics) = match clocks {
+        ClockEvents::Only => (Vec::new(), Vec::new()),
+        _ => events(&org, &locations, file_path, options),
     };
+    if clocks != ClockEvents::Exclude {
+        let (clock_events, clock_diagnostics) = clock_events(&org, &locations, file_path, options);
+        fullcalendar_events.extend(clock_events);
+        // Both passes report the same problems
+        if clocks == ClockEvents::Only {
+            diagnostics = clock_diagnostics;
+        }
+    }
+    (fullcalendar_events, diagnostics)
+}
 
-    let org = Org::parse(&s);
-    let fullcalendar_events =
-        parsed_org_to_fc_vec(org, ignore_before_days, ignore_after_days, &file);
-    Ok(fullcalendar_events)
+/// Make a FC Event vector from the sources of a configuration, with its
+/// options, and the problems found
+pub fn config_to_fc_vec(
+    config: &Config,
+) -> std::result::Result<(Vec<FCEvent>, Vec<Diagnostic>), Box<dyn Error>> {
+    let paths = config.sources.paths()?;
+    Ok(scan(
+        &paths,
+        &config.options,
+        config.output.clocks,
+        &config.scan,
+    )?)
 }
 
-/// Make a FC Event vector from all Org files in a directory.
+/// Convert the sources of a configuration and write the events to its
+/// output file, or else to `out`, returning the problems found
+pub fn config_to_fc_json(
+    config: &Config,
+    out: &mut impl Write,
+) -> std::result::Result<Vec<Diagnostic>, Box<dyn Error>> {
+    let (fullcalendar_events, diagnostics) = config_to_fc_vec(config)?;
+    config.output.write(&fullcalendar_events, out)?;
+    Ok(diagnostics)
+}
+
+/// Make a FC Event vector from all Org files in a directory and its
+/// subdirectories, but hidden and ignored ones.
 pub fn org_dir_to_fc_json_vec(
     dir: &str,
     ignore_before_days: i64,
     ignore_after_days: i64,
 ) -> std::result::Result<Vec<FCEvent>, Box<dyn Error>> {
-    let mut parsed_json_vec: Vec<FCEvent> = Vec::new();
-    let glob_pattern = format!("{}/*.org", dir);
-    let files = glob(&glob_pattern);
-    let files = match files {
-        Ok(f) => f.map(|e| e.unwrap()).collect::<Vec<_>>(),
-        Err(e) => {
-            println!("Application error: {e}");
-            std::process::exit(1);
-        }
-    };
-    for file in files {
+    let files = org_files(&[dir], &WalkOptions::default())?;
+    for file in &files {
         eprintln!("{:?}", file);
-        match orgfile_to_fc_json_vec(
-            &file.into_os_string().into_string().unwrap(),
+    }
+    let options = ConvertOptions::new(ignore_before_days, ignore_after_days);
+    let (parsed_json_vec, diagnostics) = scan(
+        &files,
+        &options,
+        ClockEvents::Exclude,
+        &ScanOptions::default(),
+    )?;
+    print_diagnostics(&diagnostics);
+    Ok(parsed_json_vec)
+}
+
+/// Make a FC Event vector from the Org files of the agenda, listed as by
+/// `org-agenda-files` in a text file
+pub fn org_agenda_files_to_fc_json_vec(
+    agenda_file: &str,
+    ignore_before_days: i64,
+    ignore_after_days: i64,
+) -> std::result::Result<Vec<FCEvent>, Box<dyn Error>> {
+    let mut parsed_json_vec: Vec<FCEvent> = Vec::new();
+    let agenda_files = AgendaFiles::File(agenda_file.to_string());
+    for file in agenda_files.paths(Path::new(""))? {
+        parsed_json_vec.extend(orgfile_to_fc_json_vec(
+            &file.to_string_lossy(),
             ignore_before_days,
             ignore_after_days,
-        ) {
-            Ok(data) => {
-                parsed_json_vec.extend(data);
-            }
-            Err(e) => {
-                println!("Application error: {e}");
-                std::process::exit(1);
-            }
-        }
+        )?);
     }
     Ok(parsed_json_vec)
 }
@@ -296,8 +571,20 @@ pub fn org_dir_to_fc_json_vec(
 mod tests {
     use chrono::DateTime;
     use chrono::Local;
+    use chrono::NaiveDate;
 
     use super::*;
+    use crate::body::DescriptionSource;
+    use crate::category::{PaletteKey, DEFAULT_PALETTE};
+    use crate::editor::LinkScheme;
+    use crate::filter::{Filter, PropertyFilter};
+    use crate::fullcalendar::FCStart;
+    use crate::inheritance::PropertyInheritance;
+    use crate::markup::TitleMarkup;
+    use crate::options::{TimestampEvents, DEFAULT_DONE_COLOR};
+    use crate::prefix::{KindDisplay, TimestampKind};
+    use crate::priority::sort_by_priority;
+    use crate::template::Template;
 
     #[test]
     fn print_deadline_wo_time_wo_range_wo_repeater_wo_desc() {
@@ -309,15 +596,16 @@ DEADLINE: <2022-07-25 Mon>
 :FC_TXT_COLOR: red
 :END:
 "#;
-        let expected = r#"[
+        let expected = r##"[
   {
+    "id": "40c281b1cab25bb0-DL",
     "title": "DL: Deadlin, Withtout Time, Without Range, Repeater: None",
     "start": "2022-07-25",
     "description": "Deadlin, Withtout Time, Without Range, Repeater: None",
-    "color": "black",
-    "textColor": "red"
+    "color": "#000000",
+    "textColor": "#ff0000"
   }
-]"#;
+]"##;
         assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
     }
 
@@ -329,6 +617,7 @@ DEADLINE: <2022-07-25 Mon +3d>
 "#;
         let expected = r#"[
   {
+    "id": "25577e450f6fe9e6-DL",
     "title": "DL: Deadlin, Withtout Time, Without Range, Repeater: +3d",
     "rrule": {
       "dtstart": "2022-07-25",
@@ -352,16 +641,17 @@ DEADLINE: <2022-07-26 Tue>--<2022-07-28 Thu>
 :FC_TXT_COLOR: black
 :END:
 "#;
-        let expected = r#"[
+        let expected = r##"[
   {
+    "id": "2fa320d962e9b431-DL",
     "title": "DL: Deadlin, Withtout Time, With Range,    Repeater: None",
     "start": "2022-07-26T00:00:00",
     "end": "2022-07-28T00:00:00",
     "description": "Deadlin, Withtout Time, With Range,    Repeater: None",
-    "color": "red",
-    "textColor": "black"
+    "color": "#ff0000",
+    "textColor": "#000000"
   }
-]"#;
+]"##;
         assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
     }
 
@@ -373,6 +663,7 @@ DEADLINE: <2022-07-16 Sat 12:00>
   "#;
         let expected = r#"[
   {
+    "id": "0ba7dae6ee231d0d-DL",
     "title": "DL: Deadline With Time,     Without Range and with a Time",
     "start": "2022-07-16T12:00:00",
     "description": "Deadline With Time,     Without Range and with a Time"
@@ -392,6 +683,7 @@ DEADLINE: <2022-07-25 Mon 18:00-22:00>
 "#;
         let expected = r#"[
   {
+    "id": "7b47f6327b09332d-DL",
     "title": "DL: Deadlin, With Time,    With Range, Repeater: None",
     "start": "2022-07-25T18:00:00",
     "end": "2022-07-25T22:00:00",
@@ -412,6 +704,7 @@ DEADLINE: <2022-07-26 Tue 10:00-11:00 +1w>
 "#;
         let expected = r#"[
   {
+    "id": "a908358fd6421ce7-DL",
     "title": "DL: Deadlin, With Time,    With Range, Repeater: +1w",
     "rrule": {
       "dtstart": "2022-07-26T10:00:00",
@@ -437,15 +730,16 @@ SCHEDULED: <2022-07-19 Tue>
 :FC_TXT_COLOR: green
 :END:
 "#;
-        let expected = r#"[
+        let expected = r##"[
   {
+    "id": "8c7c0edfa2486999-SCL",
     "title": "SCL: Scheduled, Withtout Time, Without Range, Repeater: None",
     "start": "2022-07-19",
     "description": "Scheduled, Withtout Time, Without Range, Repeater: None",
-    "color": "blue",
-    "textColor": "green"
+    "color": "#0000ff",
+    "textColor": "#008000"
   }
-]"#;
+]"##;
         assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
     }
 
@@ -457,6 +751,7 @@ SCHEDULED: <2022-07-27 Wed +1w>
 "#;
         let expected = r#"[
   {
+    "id": "578415ce40d0ec13-SCL",
     "title": "SCL: Scheduled, Withtout Time, Without Range, Repeater: +1w",
     "rrule": {
       "dtstart": "2022-07-27",
@@ -480,16 +775,17 @@ SCHEDULED: <2022-07-18 Mon>--<2022-07-21 Thu>
 :FC_TXT_COLOR: blue
 :END:
 "#;
-        let expected = r#"[
+        let expected = r##"[
   {
+    "id": "8a3abfd0cf791f88-SCL",
     "title": "SCL: Scheduled, Withtout Time, With Range,    Repeater: None",
     "start": "2022-07-18T00:00:00",
     "end": "2022-07-21T00:00:00",
     "description": "Scheduled, Withtout Time, With Range,    Repeater: None",
-    "color": "green",
-    "textColor": "blue"
+    "color": "#008000",
+    "textColor": "#0000ff"
   }
-]"#;
+]"##;
         assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
     }
 
@@ -501,6 +797,7 @@ SCHEDULED: <2022-07-28 Thu 12:00>
 "#;
         let expected = r#"[
   {
+    "id": "b2a0adf137eefe90-SCL",
     "title": "SCL: Scheduled, With Time,     Without Range, Repeater: None",
     "start": "2022-07-28T12:00:00",
     "description": "Scheduled, With Time,     Without Range, Repeater: None"
@@ -517,6 +814,7 @@ SCHEDULED: <2022-07-27 Wed 10:00-11:30>
 "#;
         let expected = r#"[
   {
+    "id": "96f9236ae92ed1ab-SCL",
     "title": "SCL: Scheduled, With Time,     With Range,    Repeater: None",
     "start": "2022-07-27T10:00:00",
     "end": "2022-07-27T11:30:00",
@@ -539,6 +837,7 @@ SCHEDULED: <2022-07-27 Wed 17:00-19:21 +1d>
 "#;
         let expected = r#"[
   {
+    "id": "2e8a1478-54f7-45f6-9fa2-2db7472a66a2-SCL",
     "title": "SCL: Scheduled, With Time,     With Range,    Repeater: +1d, With Multiline DEDC",
     "rrule": {
       "dtstart": "2022-07-27T17:00:00",
@@ -548,12 +847,763 @@ SCHEDULED: <2022-07-27 Wed 17:00-19:21 +1d>
     "start": "2022-07-27T17:00:00",
     "end": "2022-07-27T19:21:00",
     "duration": "2:21:00",
-    "description": "description for this header<br>additional description (into next line<br>"
+    "description": "description for this header<br>additional description (into next line<br>",
+    "extendedProps": {
+      "properties": {
+        "ID": "2e8a1478-54f7-45f6-9fa2-2db7472a66a2"
+      }
+    }
   }
 ]"#;
         assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
     }
 
+    #[test]
+    fn print_schedule_wt_fc_properties() {
+        let input = r#"
+** Scheduled, With FullCalendar Properties
+SCHEDULED: <2022-07-29 Fri>
+:PROPERTIES:
+:FC_GROUP:     meetings
+:FC_ALLDAY:    t
+:FC_URL:       https://example.com/meetings
+:FC_CLASS:     meeting  weekly
+:FC_EDITABLE:  nil
+:FC_DISPLAY:   background
+:FC_BORDER_COLOR: gray
+:FC_OVERLAP:   no
+:FC_RESOURCES: room-a,room-b
+:FC_DAYS_OF_WEEK: Mon 5
+:FC_START_TIME: 10:00
+:FC_END_RECUR: <2022-09-30 Fri>
+:LOCATION:     Room A
+:END:
+"#;
+        let expected = r##"[
+  {
+    "id": "2ffffc4c6ea0c87c-SCL",
+    "groupId": "meetings",
+    "allDay": true,
+    "title": "SCL: Scheduled, With FullCalendar Properties",
+    "start": "2022-07-29",
+    "daysOfWeek": [
+      1,
+      5
+    ],
+    "startTime": "10:00:00",
+    "endRecur": "2022-09-30",
+    "url": "https://example.com/meetings",
+    "classNames": [
+      "meeting",
+      "weekly"
+    ],
+    "editable": false,
+    "display": "background",
+    "description": "Scheduled, With FullCalendar Properties",
+    "borderColor": "#808080",
+    "overlap": false,
+    "resourceIds": [
+      "room-a",
+      "room-b"
+    ],
+    "extendedProps": {
+      "properties": {
+        "LOCATION": "Room A"
+      }
+    }
+  }
+]"##;
+        assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
+    }
+
+    #[test]
+    fn print_schedule_wt_fc_rrule() {
+        let input = r#"
+* Team meeting
+SCHEDULED: <2022-08-11 Thu 15:00-16:00>
+:PROPERTIES:
+:FC_RRULE: FREQ=MONTHLY;BYDAY=2TH,4TH;UNTIL=20221231
+:END:
+"#;
+        let expected = r#"[
+  {
+    "id": "aa9c703db4bdfa65-SCL",
+    "title": "SCL: Team meeting",
+    "rrule": {
+      "dtstart": "2022-08-11T15:00:00",
+      "freq": "monthly",
+      "interval": 1,
+      "byweekday": [
+        "th"
+      ],
+      "bysetpos": [
+        2,
+        4
+      ],
+      "until": "2022-12-31"
+    },
+    "start": "2022-08-11T15:00:00",
+    "end": "2022-08-11T16:00:00",
+    "duration": "1:00:00",
+    "description": "Team meeting"
+  }
+]"#;
+        assert_eq!(org_to_fc_json(input, 0, 0, ""), expected);
+    }
+
+    #[test]
+    fn end_and_thin_out_repeating_entries() {
+        let input = r#"
+* TODO Yoga
+SCHEDULED: <2022-08-01 Mon 18:00 +1w>
+:PROPERTIES:
+:FC_EXDATE: 2022-08-15, 2022-08-29 18:00
+:FC_UNTIL: 2022-09-30
+:END:
+:LOGBOOK:
+- State "CANCELLED"  from "TODO"       <2022-08-22 Mon 18:00>
+:END:
+* DONE Course
+CLOSED: [2022-09-05 Mon 10:00] SCHEDULED: <2022-08-01 Mon +1w>
+"#;
+        let (events, diagnostics) =
+            parsed_org_to_fc_vec_with_diagnostics(Org::parse(input), 0, 0, "");
+        assert!(diagnostics.is_empty());
+
+        let yoga = &events[0];
+        let at_six = |day: u32| {
+            FCStart::DateTime(
+                NaiveDate::from_ymd_opt(2022, 8, day)
+                    .unwrap()
+                    .and_hms_opt(18, 0, 0)
+                    .unwrap(),
+            )
+        };
+        assert_eq!(yoga.exdate, [at_six(15), at_six(29), at_six(22)]);
+        assert_eq!(
+            yoga.rrule.as_ref().unwrap().until,
+            Some(FCStart::DateTime(
+                NaiveDate::from_ymd_opt(2022, 9, 30)
+                    .unwrap()
+                    .and_hms_opt(23, 59, 59)
+                    .unwrap()
+            ))
+        );
+
+        let course = &events[1];
+        assert_eq!(
+            course.rrule.as_ref().unwrap().until,
+            Some(FCStart::Day(NaiveDate::from_ymd_opt(2022, 9, 5).unwrap()))
+        );
+    }
+
+    #[test]
+    fn end_date_wins_over_count() {
+        let input = r#"
+* TODO Standup
+SCHEDULED: <2022-08-01 Mon 09:00 +1d>
+:PROPERTIES:
+:FC_COUNT: 10
+:FC_UNTIL: 2022-08-05
+:END:
+"#;
+        let (events, diagnostics) =
+            parsed_org_to_fc_vec_with_diagnostics(Org::parse(input), 0, 0, "");
+        let rrule = events[0].rrule.as_ref().unwrap();
+        assert_eq!(rrule.count, None);
+        assert!(rrule.until.is_some());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "FC_COUNT is ignored: the series ends at FC_UNTIL"
+        );
+    }
+
+    #[test]
+    fn inherit_tags_and_filetags() {
+        let input = r#"#+FILETAGS: :work:
+
+* Project X                                                 :client_a:
+** TODO Call                                                  :@phone:
+SCHEDULED: <2022-08-01 Mon 10:00>
+:PROPERTIES:
+:FC_CLASS: important
+:END:
+"#;
+        let events = parsed_org_to_fc_vec(Org::parse(input), 0, 0, "");
+        assert_eq!(
+            events[0].class_names,
+            ["important", "tag-work", "tag-client_a", "tag-phone"]
+        );
+        assert_eq!(
+            events[0].extended_props["tags"],
+            serde_json::json!(["work", "client_a", "@phone"])
+        );
+    }
+
+    #[test]
+    fn filter_headlines() {
+        let input = r#"#+EXCLUDE_TAGS: private
+#+TODO: TODO NEXT | DONE
+* Work                                                              :work:
+** TODO [#A] Report
+SCHEDULED: <2022-08-01 Mon>
+** NEXT Review
+SCHEDULED: <2022-08-02 Tue>
+:PROPERTIES:
+:CLIENT: acme
+:END:
+* Home                                                              :home:
+** TODO Laundry
+SCHEDULED: <2022-08-03 Wed>
+** Diary                                                         :private:
+SCHEDULED: <2022-08-04 Thu>
+* Old                                                            :ARCHIVE:
+** TODO Old task
+SCHEDULED: <2022-08-05 Fri>
+"#;
+        let titles = |filter: Filter| -> Vec<String> {
+            let options = ConvertOptions {
+                filter,
+                ..ConvertOptions::default()
+            };
+            let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+            events.into_iter().map(|event| event.title).collect()
+        };
+
+        assert_eq!(
+            titles(Filter::default()),
+            ["SCL: TODO Report", "SCL: NEXT Review", "SCL: TODO Laundry"]
+        );
+        assert_eq!(
+            titles(Filter {
+                include_tags: vec!["work".to_string()],
+                ..Filter::default()
+            }),
+            ["SCL: TODO Report", "SCL: NEXT Review"]
+        );
+        assert_eq!(
+            titles(Filter {
+                exclude_keywords: vec!["TODO".to_string()],
+                ..Filter::default()
+            }),
+            ["SCL: NEXT Review"]
+        );
+        assert_eq!(
+            titles(Filter {
+                include_priorities: vec!['A'],
+                ..Filter::default()
+            }),
+            ["SCL: TODO Report"]
+        );
+        assert_eq!(
+            titles(Filter {
+                include_properties: vec![PropertyFilter::new("CLIENT", Some("acme"))],
+                ..Filter::default()
+            }),
+            ["SCL: NEXT Review"]
+        );
+        assert!(titles(Filter {
+            max_level: Some(1),
+            ..Filter::default()
+        })
+        .is_empty());
+        assert_eq!(
+            titles(Filter {
+                include_tags: vec!["home".to_string()],
+                keep_excluded: true,
+                ..Filter::default()
+            }),
+            ["SCL: TODO Laundry", "SCL: Diary"]
+        );
+        assert_eq!(
+            titles(Filter {
+                exclude_tags: vec!["work".to_string(), "home".to_string()],
+                keep_archived: true,
+                ..Filter::default()
+            }),
+            ["SCL: TODO Old task"]
+        );
+    }
+
+    #[test]
+    fn select_headlines_with_query() {
+        let input = r#"#+TODO: TODO NEXT | DONE
+* Client A                                                        :client:
+** NEXT Call
+SCHEDULED: <2022-08-01 Mon>
+** NEXT Invoice
+SCHEDULED: <2022-09-01 Thu>
+* NEXT Gym
+SCHEDULED: <2022-08-02 Tue>
+"#;
+        let titles = |options: ConvertOptions| -> Vec<String> {
+            let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+            events.into_iter().map(|event| event.title).collect()
+        };
+
+        let options = ConvertOptions {
+            query: Some(
+                r#"(and (todo "NEXT") (tags "client") (scheduled :from "2022-07-25" :to "2022-08-14"))"#
+                    .parse()
+                    .unwrap(),
+            ),
+            ..ConvertOptions::default()
+        };
+        assert_eq!(titles(options), ["SCL: NEXT Call"]);
+
+        let options: ConvertOptions =
+            serde_json::from_str(r#"{"query": "todo:NEXT !tags:client"}"#).unwrap();
+        assert_eq!(titles(options), ["SCL: NEXT Gym"]);
+    }
+
+    #[test]
+    fn inherit_style_properties() {
+        let input = r#"#+PROPERTY: FC_TXT_COLOR white
+* Project
+:PROPERTIES:
+:FC_BG_COLOR: #336699
+:LOCATION:    Office
+:END:
+** Task
+SCHEDULED: <2022-08-01 Mon>
+*** Subtask
+SCHEDULED: <2022-08-02 Tue>
+:PROPERTIES:
+:FC_BG_COLOR: #993366
+:END:
+"#;
+        let events = parsed_org_to_fc_vec(Org::parse(input), 0, 0, "");
+        let colors: Vec<(Option<String>, Option<String>)> = events
+            .iter()
+            .map(|event| (event.color.clone(), event.text_color.clone()))
+            .collect();
+        let white = Some("#ffffff".to_string());
+        assert_eq!(
+            colors,
+            [
+                (Some("#336699".to_string()), white.clone()),
+                (Some("#993366".to_string()), white)
+            ]
+        );
+        assert!(events[0].extended_props.get("properties").is_none());
+
+        let options = ConvertOptions {
+            inherit_properties: PropertyInheritance::Only(vec!["LOCATION".to_string()]),
+            ..ConvertOptions::default()
+        };
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].color, None);
+        assert_eq!(
+            events[0].extended_props["properties"]["LOCATION"],
+            serde_json::json!("Office")
+        );
+    }
+
+    #[test]
+    fn todo_keywords_and_done_items() {
+        let input = r#"#+TODO: TODO NEXT WAIT | DONE CANCELLED
+* WAIT Answer
+SCHEDULED: <2022-08-01 Mon>
+* CANCELLED Trip
+SCHEDULED: <2022-08-02 Tue>
+:PROPERTIES:
+:FC_BG_COLOR: blue
+:END:
+:LOGBOOK:
+CLOCK: [2022-07-28 Thu 10:00]--[2022-07-28 Thu 11:00] =>  1:00
+:END:
+"#;
+        let mut options = ConvertOptions::default();
+        options
+            .todo_colors
+            .insert("WAIT".to_string(), "orange".parse().unwrap());
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].color.as_deref(), Some("#ffa500"));
+        assert_eq!(events[0].extended_props["done"], serde_json::json!(false));
+        assert_eq!(events[1].title, "SCL: CANCELLED Trip");
+        assert_eq!(events[1].color.as_deref(), Some("#0000ff"));
+        assert_eq!(
+            events[1].extended_props["todo"],
+            serde_json::json!("CANCELLED")
+        );
+        assert_eq!(events[1].extended_props["done"], serde_json::json!(true));
+        assert_eq!(events[1].class_names, ["done"]);
+
+        options.done_items = DoneItems::Grey;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[1].color.as_deref(), Some(DEFAULT_DONE_COLOR));
+
+        options.done_items = DoneItems::Hide;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events.len(), 1);
+        let (clocks, _) = org_to_fc_clock_vec_with_options(input, "", &options);
+        assert_eq!(clocks.len(), 1);
+    }
+
+    #[test]
+    fn priorities_in_titles_colors_and_order() {
+        let input = r#"#+PRIORITIES: A E C
+* TODO [#A] Urgent
+SCHEDULED: <2022-08-01 Mon 10:00>
+* TODO Normal
+SCHEDULED: <2022-08-01 Mon 09:00>
+* [#E] Later
+SCHEDULED: <2022-08-01 Mon 08:00>
+"#;
+        let mut options = ConvertOptions::default();
+        options.priority.title_marker = true;
+        options.priority.rank_default = true;
+        options
+            .priority
+            .colors
+            .insert("A".to_string(), "red".parse().unwrap());
+        let (mut events, _) = org_to_fc_vec_with_options(input, "", &options);
+        sort_by_priority(&mut events);
+
+        let titles: Vec<&str> = events.iter().map(|event| event.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            [
+                "SCL: TODO [#A] Urgent",
+                "SCL: TODO Normal",
+                "SCL: [#E] Later"
+            ]
+        );
+        let ranks: Vec<&serde_json::Value> = events
+            .iter()
+            .map(|event| &event.extended_props["priorityRank"])
+            .collect();
+        assert_eq!(ranks, [0, 2, 4]);
+        assert_eq!(events[0].extended_props["priority"], serde_json::json!("A"));
+        assert_eq!(events[0].color.as_deref(), Some("#ff0000"));
+        assert!(events[1].extended_props.get("priority").is_none());
+    }
+
+    #[test]
+    fn categories_and_palette_colors() {
+        let input = r#"
+* Errands
+SCHEDULED: <2022-08-01 Mon>
+* Project
+:PROPERTIES:
+:CATEGORY: proj
+:END:
+** Task
+SCHEDULED: <2022-08-02 Tue>
+"#;
+        let mut options = ConvertOptions::default();
+        options.colors.palette_by = PaletteKey::Category;
+        options
+            .colors
+            .fixed
+            .insert("proj".to_string(), "#ffff00".parse().unwrap());
+        let (events, _) = org_to_fc_vec_with_options(input, "/notes/home.org", &options);
+        let categories: Vec<&serde_json::Value> = events
+            .iter()
+            .map(|event| &event.extended_props["category"])
+            .collect();
+        assert_eq!(categories, ["home", "proj"]);
+        assert_eq!(events[1].color.as_deref(), Some("#ffff00"));
+        assert_eq!(events[1].text_color.as_deref(), Some("#000000"));
+        assert!(DEFAULT_PALETTE.contains(&events[0].color.as_deref().unwrap()));
+
+        let input = format!("#+CATEGORY: chores\n{}", input);
+        let (events, _) = org_to_fc_vec_with_options(&input, "/notes/home.org", &options);
+        assert_eq!(
+            events[0].extended_props["category"],
+            serde_json::json!("chores")
+        );
+    }
+
+    #[test]
+    fn validate_and_normalize_colors() {
+        let input = r#"
+* Typos
+SCHEDULED: <2022-08-01 Mon>
+:PROPERTIES:
+:FC_BG_COLOR: gren
+:FC_BORDER_COLOR: #12345
+:FC_TXT_COLOR: Dark Slate Gray
+:END:
+"#;
+        let options = ConvertOptions::default();
+        let (events, diagnostics) = org_to_fc_vec_with_options(input, "notes.org", &options);
+        assert_eq!(events[0].color, None);
+        assert_eq!(events[0].border_color, None);
+        assert_eq!(events[0].text_color.as_deref(), Some("#2f4f4f"));
+        let messages: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                r#"notes.org: "Typos": FC_BG_COLOR: unknown color "gren""#,
+                r##"notes.org: "Typos": FC_BORDER_COLOR: invalid hex color "#12345""##,
+            ]
+        );
+    }
+
+    #[test]
+    fn render_title_markup() {
+        let input = r#"
+* TODO [#A] Fix [[https://example.com/42][Ticket 42]] *now* [1/3]
+SCHEDULED: <2022-08-01 Mon>
+"#;
+        let mut options = ConvertOptions::default();
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(
+            events[0].title,
+            "SCL: TODO Fix [[https://example.com/42][Ticket 42]] *now* [1/3]"
+        );
+        assert_eq!(events[0].url, None);
+
+        options.title.markup = TitleMarkup::Html;
+        options.title.link_url = true;
+        options.priority.title_marker = true;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "SCL: TODO [#A] Fix Ticket 42 now");
+        assert_eq!(
+            events[0].extended_props["titleHtml"],
+            serde_json::json!(
+                "SCL: TODO [#A] Fix <a href=\"https://example.com/42\">Ticket 42</a> <b>now</b>"
+            )
+        );
+        assert_eq!(events[0].url.as_deref(), Some("https://example.com/42"));
+    }
+
+    #[test]
+    fn render_body_as_description() {
+        let input = r#"
+* Review
+SCHEDULED: <2022-08-01 Mon>
+:PROPERTIES:
+:DESCRIPTION: Not shown
+:END:
+:LOGBOOK:
+CLOCK: [2022-07-28 Thu 10:00]--[2022-07-28 Thu 11:00] =>  1:00
+:END:
+Check the *numbers* in [[https://example.com][the report]].
+- Sales
+- Costs
+* Call
+SCHEDULED: <2022-08-02 Tue>
+:PROPERTIES:
+:DESCRIPTION: Ask <Bob>
+:END:
+"#;
+        let mut options = ConvertOptions::default();
+        options.description.source = DescriptionSource::Plain;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(
+            events[0].description.as_deref(),
+            Some("Check the numbers in the report.\n- Sales\n- Costs")
+        );
+        assert_eq!(events[1].description.as_deref(), Some("Ask <Bob>"));
+
+        options.description.source = DescriptionSource::Html;
+        options.description.max_length = Some(20);
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(
+            events[0].description.as_deref(),
+            Some("<p>Check the <b>numbers</b> in…</p>")
+        );
+        assert_eq!(events[1].description.as_deref(), Some("Ask &lt;Bob&gt;"));
+    }
+
+    #[test]
+    fn title_and_description_templates() {
+        let input = r#"#+FC_TITLE_TEMPLATE: {title} ({OWNER})
+* Project X
+** TODO [#B] Review <draft>                                       :work:
+SCHEDULED: <2022-08-01 Mon 10:00-11:30>
+:PROPERTIES:
+:OWNER:    Ann & Bob
+:LOCATION: Room 2
+:END:
+"#;
+        let mut options = ConvertOptions::default();
+        options.templates.title = Some(Template::parse("{prefix}{keyword} {title}"));
+        options.templates.description = Some(Template::parse(
+            "Owner: {OWNER} / Room: {LOCATION} / {outline_path} / {duration} / {tags}",
+        ));
+        let (events, _) = org_to_fc_vec_with_options(input, "/notes/work.org", &options);
+        assert_eq!(events[0].title, "Review <draft> (Ann & Bob)");
+        assert_eq!(
+            events[0].description.as_deref(),
+            Some("Owner: Ann &amp; Bob / Room: Room 2 / Project X / Review &lt;draft&gt; / 1:30 / work")
+        );
+
+        let input = input.replace("#+FC_TITLE_TEMPLATE: {title} ({OWNER})\n", "");
+        options.description.source = DescriptionSource::Plain;
+        options.templates.description = Some(Template::parse("{file}: {start_time}-{end_time}"));
+        let (events, _) = org_to_fc_vec_with_options(&input, "/notes/work.org", &options);
+        assert_eq!(events[0].title, "SCL: TODO Review <draft>");
+        assert_eq!(
+            events[0].description.as_deref(),
+            Some("work.org: 10:00-11:30")
+        );
+    }
+
+    #[test]
+    fn localized_prefixes_and_kinds() {
+        let input = r#"#+LANGUAGE: ja
+* TODO Report
+DEADLINE: <2022-08-05 Fri> SCHEDULED: <2022-08-01 Mon>
+"#;
+        let mut options = ConvertOptions::default();
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        let titles: Vec<&str> = events.iter().map(|event| event.title.as_str()).collect();
+        assert_eq!(titles, ["締切: TODO Report", "予定: TODO Report"]);
+
+        options.prefixes.locale = Some("en".to_string());
+        options
+            .prefixes
+            .prefixes
+            .insert(TimestampKind::Deadline, "⏰ ".to_string());
+        options.prefixes.show_kind = KindDisplay::Both;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "⏰ TODO Report");
+        assert_eq!(events[1].title, "SCL: TODO Report");
+        assert_eq!(
+            events[0].extended_props["kind"],
+            serde_json::json!("deadline")
+        );
+        assert_eq!(events[1].class_names, ["kind-scheduled"]);
+
+        options.prefixes.show_kind = KindDisplay::Props;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "TODO Report");
+
+        // A title written like a prefix is kept as it is
+        let input = "* DL: SCL: Report\nSCHEDULED: <2022-08-01 Mon>\n";
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "DL: SCL: Report");
+        options.prefixes.show_kind = KindDisplay::Prefix;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "SCL: DL: SCL: Report");
+    }
+
+    #[test]
+    fn events_of_closed_lines_and_timestamps() {
+        let input = r#"* DONE Release <2022-08-03 Wed>
+CLOSED: [2022-08-04 Thu 18:00]
+:LOGBOOK:
+- Note taken on [2022-08-02 Tue 10:00]
+:END:
+Call back <2022-08-08 Mon 09:00>--<2022-08-08 Mon 10:00>, met [2022-08-01 Mon].
+"#;
+        let mut options = ConvertOptions::default();
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert!(events.is_empty());
+
+        options.timestamp_events = TimestampEvents {
+            closed: true,
+            active: true,
+            inactive: true,
+        };
+        options.prefixes.show_kind = KindDisplay::Both;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        let starts: Vec<String> = events.iter().map(|e| e.start.to_string()).collect();
+        assert_eq!(
+            starts,
+            [
+                "2022-08-04T18:00:00",
+                "2022-08-03",
+                "2022-08-08T09:00:00",
+                "2022-08-01"
+            ]
+        );
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|e| e.extended_props["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["closed", "timestamp", "timestamp", "inactive"]);
+        assert_eq!(events[0].title, "CLOSED: DONE Release <2022-08-03 Wed>");
+        assert_eq!(events[1].title, "DONE Release <2022-08-03 Wed>");
+        assert_eq!(
+            events[2].end,
+            Some(
+                NaiveDate::from_ymd_opt(2022, 8, 8)
+                    .unwrap()
+                    .and_hms_opt(10, 0, 0)
+                    .unwrap()
+            )
+        );
+        let inactive_id = events[3].id.clone();
+        assert!(inactive_id.as_deref().unwrap().ends_with("-IA0"));
+
+        // The ids do not depend on the other kinds
+        options.timestamp_events = TimestampEvents {
+            inactive: true,
+            ..TimestampEvents::default()
+        };
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, inactive_id);
+    }
+
+    #[test]
+    fn outline_metadata_and_breadcrumb() {
+        let input = r#"* Project /X/
+** TODO [#A] Review :work:
+SCHEDULED: <2022-08-01 Mon>
+:PROPERTIES:
+:ID: 1234-abcd
+:END:
+"#;
+        let mut options = ConvertOptions::default();
+        options.metadata.extended_props = true;
+        options.metadata.breadcrumb = true;
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "SCL: TODO Project /X/ › Review");
+        let props = &events[0].extended_props;
+        assert_eq!(props["outlinePath"], serde_json::json!(["Project /X/"]));
+        assert_eq!(props["level"], serde_json::json!(2));
+        assert_eq!(props["line"], serde_json::json!(2));
+        assert_eq!(props["offset"], serde_json::json!(14));
+        assert_eq!(props["orgId"], serde_json::json!("1234-abcd"));
+
+        options.title.markup = TitleMarkup::Html;
+        options.metadata.breadcrumb_separator = " > ".to_string();
+        let (events, _) = org_to_fc_vec_with_options(input, "", &options);
+        assert_eq!(events[0].title, "SCL: TODO Project X > Review");
+        assert_eq!(
+            events[0].extended_props["titleHtml"],
+            serde_json::json!("SCL: TODO Project <i>X</i> &gt; Review")
+        );
+        assert_eq!(
+            events[0].extended_props["outlinePath"],
+            serde_json::json!(["Project X"])
+        );
+    }
+
+    #[test]
+    fn editor_links() {
+        let input = r#"* Notes
+** Review
+SCHEDULED: <2022-08-01 Mon>
+:PROPERTIES:
+:ID: 1234-abcd
+:FC_URL: https://example.com
+:END:
+"#;
+        let mut options = ConvertOptions::default();
+        options.editor_links.scheme = Some(LinkScheme::Vscode);
+        let (events, _) = org_to_fc_vec_with_options(input, "/home/me/notes.org", &options);
+        assert_eq!(events[0].url.as_deref(), Some("https://example.com"));
+        assert_eq!(
+            events[0].extended_props["editorUrl"],
+            serde_json::json!("vscode://file/home/me/notes.org:2")
+        );
+
+        options.editor_links.scheme = Some(LinkScheme::Id);
+        options.editor_links.replace_url = true;
+        let (events, _) = org_to_fc_vec_with_options(input, "/home/me/notes.org", &options);
+        assert_eq!(events[0].url.as_deref(), Some("id:1234-abcd"));
+    }
+
     #[test]
     fn print_clock_1_wt_time_wt_range_wt_desc() {
         let input = r#"
@@ -568,6 +1618,7 @@ CLOCK: [2022-07-18 Mon 15:54]--[2022-07-18 Mon 17:07] =>  1:13
 "#;
         let expected = r#"[
   {
+    "id": "f4876311610a27e7-CLK0",
     "title": "Clock1",
     "start": "2022-07-18T15:54:00",
     "end": "2022-07-18T17:07:00",
@@ -593,6 +1644,7 @@ CLOCK: [2022-07-17 Sun 13:54]--[2022-07-18 Mon 15:07] => 25:13
 "#;
         let expected = r#"[
   {
+    "id": "1eef04654e6eb13c-CLK0",
     "title": "Clock2",
     "start": "2022-07-18T13:54:00",
     "end": "2022-07-18T14:07:00",
@@ -600,6 +1652,7 @@ CLOCK: [2022-07-17 Sun 13:54]--[2022-07-18 Mon 15:07] => 25:13
     "description": "Clock test2<br>"
   },
   {
+    "id": "877a9c162965051b-CLK1",
     "title": "Clock2",
     "start": "2022-07-17T13:54:00",
     "end": "2022-07-18T15:07:00",
@@ -623,20 +1676,134 @@ DEADLINE: <2022-07-22 Fri>
 CLOCK: [2022-07-20 Wed 15:54]--[2022-07-20 Wed 17:07] =>  1:13
 :END:
    "#;
-        let expected = r#"[
+        let expected = r##"[
   {
+    "id": "d7f38b6fcca3d9bc-CLK0",
     "title": "Clock3",
     "start": "2022-07-20T15:54:00",
     "end": "2022-07-20T17:07:00",
     "duration": "1:13:00",
     "description": "TODO Clock3",
-    "color": "yellow",
-    "textColor": "white"
+    "color": "#ffff00",
+    "textColor": "#ffffff"
   }
-]"#;
+]"##;
         assert_eq!(org_to_fc_clock_json(input, 0, 0, ""), expected);
     }
 
+    #[test]
+    fn stable_ids_for_nested_headlines() {
+        let input = r#"
+* Project X
+** Review
+DEADLINE: <2022-08-01 Mon>
+* Project Y
+** Review
+DEADLINE: <2022-08-01 Mon>
+:PROPERTIES:
+:CUSTOM_ID: review-y
+:END:
+"#;
+        let events = parsed_org_to_fc_vec(Org::parse(input), 0, 0, "");
+        let ids: Vec<&str> = events.iter().map(|e| e.id.as_deref().unwrap()).collect();
+        assert_eq!(ids, ["8d196c841f1d092a-DL", "review-y-DL"]);
+    }
+
+    #[test]
+    fn distinct_ids_for_duplicate_headlines() {
+        let input = r#"
+* Review
+DEADLINE: <2022-08-01 Mon>
+* Review
+DEADLINE: <2022-08-01 Mon>
+"#;
+        let events = parsed_org_to_fc_vec(Org::parse(input), 0, 0, "");
+        assert!(events[0].id.is_some());
+        assert_ne!(events[0].id, events[1].id);
+    }
+
+    #[test]
+    fn same_ids_for_paths_of_the_same_file() {
+        let input = "* Review\nDEADLINE: <2022-08-01 Mon>\n";
+        let absolute = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let ids: Vec<Option<String>> = ["Cargo.toml", "./Cargo.toml", absolute]
+            .iter()
+            .map(|path| {
+                parsed_org_to_fc_vec(Org::parse(input), 0, 0, path)[0]
+                    .id
+                    .clone()
+            })
+            .collect();
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(ids[0], ids[2]);
+    }
+
+    #[test]
+    fn override_and_hide_with_fc_properties() {
+        let input = r#"
+* Shown
+DEADLINE: <2022-08-02 Tue>
+:PROPERTIES:
+:FC_TITLE:   Submit the report
+:FC_ALLDAY:  maybe
+:FC_DISPLAY: hidden
+:END:
+* Hidden
+DEADLINE: <2022-08-03 Wed>
+:PROPERTIES:
+:FC_HIDE: t
+:END:
+"#;
+        let (events, diagnostics) =
+            parsed_org_to_fc_vec_with_diagnostics(Org::parse(input), 0, 0, "");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Submit the report");
+        assert_eq!(events[0].all_day, None);
+        assert_eq!(events[0].display, None);
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#""Shown": FC_ALLDAY: expected t or nil, got "maybe""#,
+                r#""Shown": FC_DISPLAY: expected one of auto, block, list-item, background, inverse-background, none, got "hidden""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn read_back_fc_json() {
+        let input = r#"
+** Scheduled, With Time,     With Range,    Repeater: +1w
+SCHEDULED: <2022-07-27 Wed 10:00-11:00 +1w>
+:PROPERTIES:
+:FC_URL: https://example.com
+:OWNER:  shimano
+:END:
+"#;
+        let json = org_to_fc_json(input, 0, 0, "");
+        let events = fc_json_to_vec(&json).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].url.as_deref(), Some("https://example.com"));
+        assert_eq!(serde_json::to_string_pretty(&events).unwrap(), json);
+    }
+
+    #[test]
+    fn sort_events_by_start() {
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2022, 7, d).unwrap();
+        let mut events = vec![
+            FCEvent::builder("b", FCStart::Day(date(26))).build(),
+            FCEvent::builder(
+                "a",
+                FCStart::DateTime(date(25).and_hms_opt(9, 0, 0).unwrap()),
+            )
+            .build(),
+            FCEvent::builder("c", FCStart::Day(date(25))).build(),
+        ];
+        events.sort();
+        let titles: Vec<&str> = events.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["c", "a", "b"]);
+    }
+
     #[test]
     fn ignore_deadline_after_1_day() {
         let now_daytime: DateTime<Local> = Local::now();
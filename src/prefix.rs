@@ -0,0 +1,194 @@
+//! Prefixes of the titles per kind of timestamp, e.g. `DL: ` for deadlines,
+//! and their translations.
+//!
+//! The locale is the option's, else the file's `#+LANGUAGE:`, else English.
+//! Only its language (`ja` of `ja-JP`) is looked at. Prefixes set in the
+//! options win over those of the locale; `"locale": "none"` leaves them all
+//! out. The kind can also be given as `extendedProps.kind` and a
+//! `kind-deadline` like class name, with or instead of the prefix.
+//!
+//! Events are made from CLOSED lines and from the timestamps in the text
+//! only when they are turned on, see
+//! [`TimestampEvents`](crate::options::TimestampEvents).
+use crate::fullcalendar::EventKind;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Prefix of the class names of kinds
+pub const KIND_CLASS_PREFIX: &str = "kind-";
+
+/// Prefixes per language, in the order of [`TimestampKind::ALL`]
+const LOCALE_PREFIXES: &[(&str, [&str; 6])] = &[
+    ("en", ["DL: ", "SCL: ", "CLOSED: ", "", "", ""]),
+    ("ja", ["締切: ", "予定: ", "完了: ", "", "", ""]),
+    ("de", ["Frist: ", "Geplant: ", "Erledigt: ", "", "", ""]),
+    ("fr", ["Échéance : ", "Prévu : ", "Terminé : ", "", "", ""]),
+    ("none", ["", "", "", "", "", ""]),
+];
+
+/// What an event is made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampKind {
+    Deadline,
+    Scheduled,
+    Closed,
+    Clock,
+    /// An active timestamp in the text
+    Timestamp,
+    /// An inactive timestamp in the text
+    Inactive,
+}
+
+impl TimestampKind {
+    pub const ALL: [TimestampKind; 6] = [
+        TimestampKind::Deadline,
+        TimestampKind::Scheduled,
+        TimestampKind::Closed,
+        TimestampKind::Clock,
+        TimestampKind::Timestamp,
+        TimestampKind::Inactive,
+    ];
+
+    /// Position in [`TimestampKind::ALL`]
+    fn index(&self) -> usize {
+        TimestampKind::ALL
+            .iter()
+            .position(|known| known == self)
+            .unwrap_or_default()
+    }
+
+    /// Name in `extendedProps.kind` and in the class name
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimestampKind::Deadline => "deadline",
+            TimestampKind::Scheduled => "scheduled",
+            TimestampKind::Closed => "closed",
+            TimestampKind::Clock => "clock",
+            TimestampKind::Timestamp => "timestamp",
+            TimestampKind::Inactive => "inactive",
+        }
+    }
+}
+
+/// Prefix of a kind in English, the default locale
+pub fn default_prefix(kind: TimestampKind) -> &'static str {
+    LOCALE_PREFIXES[0].1[kind.index()]
+}
+
+impl From<EventKind> for TimestampKind {
+    fn from(kind: EventKind) -> TimestampKind {
+        match kind {
+            EventKind::Deadline => TimestampKind::Deadline,
+            EventKind::Scheduled => TimestampKind::Scheduled,
+            EventKind::Closed => TimestampKind::Closed,
+            EventKind::Clock(..) => TimestampKind::Clock,
+            EventKind::Timestamp(..) => TimestampKind::Timestamp,
+            EventKind::Inactive(..) => TimestampKind::Inactive,
+        }
+    }
+}
+
+/// How the kind of an event is shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KindDisplay {
+    /// In front of the title
+    #[default]
+    Prefix,
+    /// As `extendedProps.kind` and a class name
+    Props,
+    /// Both
+    Both,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefixOptions {
+    /// Language of the prefixes, the file's `#+LANGUAGE:` when None
+    pub locale: Option<String>,
+    /// Prefixes instead of those of the locale
+    pub prefixes: BTreeMap<TimestampKind, String>,
+    pub show_kind: KindDisplay,
+}
+
+impl PrefixOptions {
+    /// Prefix of the titles of a kind, for a file in a language
+    pub fn prefix(&self, kind: TimestampKind, language: Option<&str>) -> String {
+        if self.show_kind == KindDisplay::Props {
+            return String::new();
+        }
+        if let Some(prefix) = self.prefixes.get(&kind) {
+            return prefix.clone();
+        }
+        let locale = self.locale.as_deref().or(language).unwrap_or("en");
+        let language = locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        LOCALE_PREFIXES
+            .iter()
+            .find(|(known, _)| *known == language)
+            .unwrap_or(&LOCALE_PREFIXES[0])
+            .1[kind.index()]
+        .to_string()
+    }
+
+    /// Give the kind as `extendedProps.kind` and a class name?
+    pub fn kind_props(&self) -> bool {
+        self.show_kind != KindDisplay::Prefix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn localized_prefixes() {
+        let options = PrefixOptions::default();
+        assert_eq!(options.prefix(TimestampKind::Deadline, None), "DL: ");
+        assert_eq!(options.prefix(TimestampKind::Clock, None), "");
+        assert_eq!(
+            options.prefix(TimestampKind::Scheduled, Some("ja-JP")),
+            "予定: "
+        );
+        assert_eq!(
+            options.prefix(TimestampKind::Scheduled, Some("xx")),
+            "SCL: "
+        );
+
+        let options = PrefixOptions {
+            locale: Some("ja".to_string()),
+            prefixes: BTreeMap::from([(TimestampKind::Deadline, "⏰ ".to_string())]),
+            ..PrefixOptions::default()
+        };
+        assert_eq!(options.prefix(TimestampKind::Deadline, Some("en")), "⏰ ");
+        assert_eq!(
+            options.prefix(TimestampKind::Scheduled, Some("en")),
+            "予定: "
+        );
+        assert_eq!(options.prefix(TimestampKind::Closed, Some("en")), "完了: ");
+
+        let options = PrefixOptions {
+            show_kind: KindDisplay::Props,
+            ..PrefixOptions::default()
+        };
+        assert_eq!(options.prefix(TimestampKind::Deadline, None), "");
+        assert!(options.kind_props());
+    }
+
+    #[test]
+    fn read_prefixes() {
+        let options: PrefixOptions =
+            serde_json::from_str(r#"{"prefixes": {"deadline": "!", "clock": "~"}}"#).unwrap();
+        assert_eq!(options.prefixes[&TimestampKind::Deadline], "!");
+        assert_eq!(options.prefixes[&TimestampKind::Clock], "~");
+        let options: PrefixOptions =
+            serde_json::from_str(r#"{"prefixes": {"closed": "✓ ", "inactive": ""}}"#).unwrap();
+        assert_eq!(options.prefix(TimestampKind::Closed, None), "✓ ");
+        assert_eq!(options.prefix(TimestampKind::Inactive, None), "");
+        assert!(serde_json::from_str::<PrefixOptions>(r#"{"prefixes": {"diary": "!"}}"#).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Priorities (`[#A]`) of headlines.
+//!
+//! Events of a headline with a priority get `extendedProps.priority` and
+//! `extendedProps.priorityRank`, 0 for the highest priority, which
+//! FullCalendar's `eventOrder: "priorityRank"` can sort on. The range and
+//! the default priority are `#+PRIORITIES: A C B` like in Org.
+use crate::color::Color;
+use crate::fullcalendar::FCEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Highest, lowest and default priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priorities {
+    pub highest: char,
+    pub lowest: char,
+    pub default: char,
+}
+
+impl Default for Priorities {
+    fn default() -> Priorities {
+        Priorities {
+            highest: 'A',
+            lowest: 'C',
+            default: 'B',
+        }
+    }
+}
+
+impl Priorities {
+    /// `#+PRIORITIES: A C B` or `1 9 5`, None when it cannot be used
+    pub fn parse(value: &str) -> Option<Priorities> {
+        let mut priorities = value.split_whitespace().map(|priority| {
+            let mut chars = priority.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        });
+        let highest = priorities.next()??;
+        let lowest = priorities.next()??;
+        let default = priorities.next().flatten().unwrap_or(highest);
+        if highest > lowest || !(highest..=lowest).contains(&default) {
+            return None;
+        }
+        Some(Priorities {
+            highest,
+            lowest,
+            default,
+        })
+    }
+
+    /// 0 for the highest priority, the default priority without one
+    pub fn rank(&self, priority: Option<char>) -> u32 {
+        let priority = priority.unwrap_or(self.default);
+        (priority as u32).saturating_sub(self.highest as u32)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriorityOptions {
+    /// Add `[#A] ` in front of the titles
+    pub title_marker: bool,
+    /// Color of the events per priority, e.g. `"A": "red"`
+    pub colors: BTreeMap<String, Color>,
+    /// Give a `priorityRank` (the default priority's) to the events of
+    /// headlines without a priority too
+    pub rank_default: bool,
+}
+
+/// Sort events by day, then by priority, then by start
+pub fn sort_by_priority(events: &mut [FCEvent]) {
+    events.sort_by_key(|event| {
+        let start = event.start.to_naive_date_time();
+        let rank = event
+            .extended_props
+            .get("priorityRank")
+            .and_then(|rank| rank.as_u64())
+            .unwrap_or(u64::MAX);
+        (start.date(), rank, start)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_priorities() {
+        assert_eq!(Priorities::parse("A C B"), Some(Priorities::default()));
+        assert_eq!(
+            Priorities::parse("1 9 5"),
+            Some(Priorities {
+                highest: '1',
+                lowest: '9',
+                default: '5'
+            })
+        );
+        assert_eq!(Priorities::parse("C A B"), None);
+        assert_eq!(Priorities::parse("A"), None);
+        assert_eq!(Priorities::parse("10 64 32"), None);
+    }
+
+    #[test]
+    fn rank_priorities() {
+        let priorities = Priorities::default();
+        assert_eq!(priorities.rank(Some('A')), 0);
+        assert_eq!(priorities.rank(Some('C')), 2);
+        assert_eq!(priorities.rank(None), 1);
+    }
+}
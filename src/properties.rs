@@ -0,0 +1,429 @@
+//! Properties in the `FC_` namespace, which set FullCalendar fields per headline.
+//!
+//! | Property             | Event field       | Value                                  |
+//! |----------------------|-------------------|----------------------------------------|
+//! | `FC_ID`              | `id`              | text                                   |
+//! | `FC_TITLE`           | `title`           | text, replaces the prefix and keyword  |
+//! | `FC_HIDE`            | (no event)        | `t` / `nil`                            |
+//! | `FC_GROUP`           | `groupId`         | text                                   |
+//! | `FC_ALLDAY`          | `allDay`          | `t` / `nil`                            |
+//! | `FC_URL`             | `url`             | Web URL, or a path                     |
+//! | `FC_CLASS`           | `classNames`      | CSS class names, before the tag classes |
+//! | `FC_EDITABLE`        | `editable`        | `t` / `nil`                            |
+//! | `FC_DISPLAY`         | `display`         | `auto`, `block`, `list-item`, `background`, `inverse-background`, `none` |
+//! | `FC_BG_COLOR`        | `color`           | color                                  |
+//! | `FC_BACKGROUND_COLOR`| `backgroundColor` | color                                  |
+//! | `FC_BORDER_COLOR`    | `borderColor`     | color                                  |
+//! | `FC_TXT_COLOR`       | `textColor`       | color                                  |
+//! | `FC_CONSTRAINT`      | `constraint`      | group id or `businessHours`            |
+//! | `FC_OVERLAP`         | `overlap`         | `t` / `nil`                            |
+//! | `FC_RESOURCES`       | `resourceIds`     | ids                                    |
+//! | `FC_DAYS_OF_WEEK`    | `daysOfWeek`      | `0`-`6` (Sunday is 0) or `Mon`, `Tue` ... |
+//! | `FC_START_TIME`      | `startTime`       | `HH:MM` or `HH:MM:SS`                  |
+//! | `FC_END_TIME`        | `endTime`         | `HH:MM` or `HH:MM:SS`                  |
+//! | `FC_START_RECUR`     | `startRecur`      | `YYYY-MM-DD` or an Org timestamp       |
+//! | `FC_END_RECUR`       | `endRecur`        | `YYYY-MM-DD` or an Org timestamp       |
+//! | `FC_RRULE`           | `rrule`           | RFC 5545 RRULE, see [`crate::rrule`]   |
+//! | `FC_EXDATE`          | `exdate`          | dates (or timestamps) to skip          |
+//! | `FC_UNTIL`           | `rrule.until`     | last date of a repeating entry         |
+//! | `FC_COUNT`           | `rrule.count`     | number of occurrences, unless the series ends on a date |
+//!
+//! Lists are separated by spaces or commas. Colors are read and written as
+//! described in [`crate::color`].
+//!
+//! Precedence, from the highest:
+//! 1. an `FC_` property on the headline itself,
+//! 2. one inherited from the nearest ancestor, for the properties which are
+//!    inherited (the colors and `FC_CLASS` by default, see [`crate::inheritance`]),
+//! 3. a `#+PROPERTY:` line of the file,
+//! 4. the value computed from the Org data (timestamps, keyword, `:ID:`,
+//!    `DESCRIPTION` ...),
+//! 5. nothing, i.e. FullCalendar's default.
+//!
+//! A value that cannot be used is reported as a [`Diagnostic`] and ignored,
+//! so the computed value is kept. So is an unknown `FC_` property, which
+//! usually is a typo.
+use crate::color::normalize_color;
+use crate::diagnostic::Diagnostic;
+use crate::fullcalendar::{FCEvent, FCStart};
+use crate::markup::{is_web_scheme, link_scheme};
+use crate::rrule::RRule;
+use crate::timestamp::naive_datetime_duration;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use orgize::elements;
+
+/// Properties this crate understands
+pub const KNOWN_PROPERTIES: &[&str] = &[
+    "FC_ID",
+    "FC_TITLE",
+    "FC_HIDE",
+    "FC_GROUP",
+    "FC_ALLDAY",
+    "FC_URL",
+    "FC_CLASS",
+    "FC_EDITABLE",
+    "FC_DISPLAY",
+    "FC_BG_COLOR",
+    "FC_BACKGROUND_COLOR",
+    "FC_BORDER_COLOR",
+    "FC_TXT_COLOR",
+    "FC_CONSTRAINT",
+    "FC_OVERLAP",
+    "FC_RESOURCES",
+    "FC_DAYS_OF_WEEK",
+    "FC_START_TIME",
+    "FC_END_TIME",
+    "FC_START_RECUR",
+    "FC_END_RECUR",
+    "FC_RRULE",
+    "FC_EXDATE",
+    "FC_UNTIL",
+    "FC_COUNT",
+];
+
+const DISPLAY_VALUES: &[&str] = &[
+    "auto",
+    "block",
+    "list-item",
+    "background",
+    "inverse-background",
+    "none",
+];
+
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Values of the `FC_` properties of a headline. `None` leaves the computed value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    /// Make no event from the headline
+    pub hide: bool,
+    pub group_id: Option<String>,
+    pub all_day: Option<bool>,
+    pub url: Option<String>,
+    pub class_names: Option<Vec<String>>,
+    pub editable: Option<bool>,
+    pub display: Option<String>,
+    pub background_color: Option<String>,
+    pub border_color: Option<String>,
+    pub constraint: Option<String>,
+    pub overlap: Option<bool>,
+    pub resource_ids: Option<Vec<String>>,
+    pub days_of_week: Option<Vec<u32>>,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub start_recur: Option<NaiveDate>,
+    pub end_recur: Option<NaiveDate>,
+    pub rrule: Option<RRule>,
+    pub exdate: Vec<FCStart>,
+    pub until: Option<NaiveDate>,
+    pub count: Option<u32>,
+}
+
+impl Overrides {
+    /// Read the `FC_` properties of a headline.
+    /// Values which cannot be used are left out and reported.
+    pub fn from_title(title: &elements::Title, file_path: &str) -> (Overrides, Vec<Diagnostic>) {
+        let mut overrides = Overrides::default();
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+        for (key, value) in title.properties.clone().into_iter() {
+            if !key.starts_with("FC_") {
+                continue;
+            }
+            let value = value.trim();
+            if let Err(message) = overrides.set(&key, value) {
+                diagnostics.push(Diagnostic::new(
+                    file_path,
+                    &title.raw,
+                    format!("{}: {}", key, message),
+                ));
+            }
+        }
+        (overrides, diagnostics)
+    }
+
+    /// Set the field for one property. The last one wins when it is repeated.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "FC_ID" => self.id = Some(parse_text(value)?),
+            "FC_TITLE" => self.title = Some(parse_text(value)?),
+            "FC_HIDE" => self.hide = parse_bool(value)?,
+            "FC_GROUP" => self.group_id = Some(parse_text(value)?),
+            "FC_ALLDAY" => self.all_day = Some(parse_bool(value)?),
+            "FC_URL" => self.url = Some(parse_url(value)?),
+            "FC_CLASS" => self.class_names = Some(parse_class_names(value)?),
+            "FC_EDITABLE" => self.editable = Some(parse_bool(value)?),
+            "FC_DISPLAY" => self.display = Some(parse_display(value)?),
+            "FC_BACKGROUND_COLOR" => self.background_color = Some(normalize_color(value)?),
+            "FC_BORDER_COLOR" => self.border_color = Some(normalize_color(value)?),
+            "FC_CONSTRAINT" => self.constraint = Some(parse_text(value)?),
+            "FC_OVERLAP" => self.overlap = Some(parse_bool(value)?),
+            "FC_RESOURCES" => self.resource_ids = Some(parse_list(value)),
+            "FC_DAYS_OF_WEEK" => self.days_of_week = Some(parse_days_of_week(value)?),
+            "FC_START_TIME" => self.start_time = Some(parse_time(value)?),
+            "FC_END_TIME" => self.end_time = Some(parse_time(value)?),
+            "FC_START_RECUR" => self.start_recur = Some(parse_date(value)?),
+            "FC_END_RECUR" => self.end_recur = Some(parse_date(value)?),
+            "FC_RRULE" => self.rrule = Some(value.parse()?),
+            "FC_EXDATE" => self.exdate = parse_dates(value)?,
+            "FC_UNTIL" => self.until = Some(parse_date(value)?),
+            "FC_COUNT" => self.count = Some(parse_count(value)?),
+            // Checked here, read by make_color / make_text_color
+            "FC_BG_COLOR" | "FC_TXT_COLOR" => {
+                normalize_color(value)?;
+            }
+            _ => return Err("unknown property".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Replace the computed values of an event
+    pub fn apply(&self, event: &mut FCEvent) {
+        fn set<T: Clone>(field: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+        fn set_some<T: Clone>(field: &mut Option<T>, value: &Option<T>) {
+            if value.is_some() {
+                *field = value.clone();
+            }
+        }
+
+        set_some(&mut event.id, &self.id);
+        set(&mut event.title, &self.title);
+        set_some(&mut event.group_id, &self.group_id);
+        set_some(&mut event.all_day, &self.all_day);
+        set_some(&mut event.url, &self.url);
+        set(&mut event.class_names, &self.class_names);
+        set_some(&mut event.editable, &self.editable);
+        set_some(&mut event.display, &self.display);
+        set_some(&mut event.background_color, &self.background_color);
+        set_some(&mut event.border_color, &self.border_color);
+        set_some(&mut event.constraint, &self.constraint);
+        set_some(&mut event.overlap, &self.overlap);
+        set(&mut event.resource_ids, &self.resource_ids);
+        set(&mut event.days_of_week, &self.days_of_week);
+        set_some(&mut event.start_time, &self.start_time);
+        set_some(&mut event.end_time, &self.end_time);
+        set_some(&mut event.start_recur, &self.start_recur);
+        set_some(&mut event.end_recur, &self.end_recur);
+
+        // The timestamp still supplies dtstart and, with a range, the duration
+        if let Some(rrule) = &self.rrule {
+            event.rrule = Some(rrule.to_fc_rrule(event.start));
+            if let (None, Some(end)) = (&event.duration, event.end) {
+                event.duration = Some(naive_datetime_duration(
+                    event.start.to_naive_date_time(),
+                    end,
+                ));
+            }
+        }
+    }
+}
+
+fn parse_text(value: &str) -> Result<String, String> {
+    if value.is_empty() {
+        return Err("empty value".to_string());
+    }
+    Ok(value.to_string())
+}
+
+/// "t", "true", "yes" or "nil", "false", "no"
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "t" | "true" | "yes" => Ok(true),
+        "nil" | "false" | "no" => Ok(false),
+        _ => Err(format!("expected t or nil, got \"{}\"", value)),
+    }
+}
+
+/// Items separated by spaces or commas
+pub fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+/// A URL of one of the [web schemes](crate::markup::WEB_SCHEMES), or a
+/// path. Anything else could run code, like `javascript://%0aalert(1)`, or
+/// be read as a host by the browser, like `example.com:8080/path`.
+fn parse_url(value: &str) -> Result<String, String> {
+    let is_url = link_scheme(value).is_some_and(is_web_scheme);
+    if is_url || value.starts_with('/') || value.starts_with('#') {
+        return Ok(value.to_string());
+    }
+    Err(format!("\"{}\" is neither a web URL nor a path", value))
+}
+
+fn parse_class_names(value: &str) -> Result<Vec<String>, String> {
+    let class_names = parse_list(value);
+    for class_name in &class_names {
+        if !class_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("\"{}\" is not a CSS class name", class_name));
+        }
+    }
+    Ok(class_names)
+}
+
+fn parse_display(value: &str) -> Result<String, String> {
+    if DISPLAY_VALUES.contains(&value) {
+        return Ok(value.to_string());
+    }
+    Err(format!(
+        "expected one of {}, got \"{}\"",
+        DISPLAY_VALUES.join(", "),
+        value
+    ))
+}
+
+/// Numbers (0 = Sunday) or day names
+pub fn parse_days_of_week(value: &str) -> Result<Vec<u32>, String> {
+    parse_list(value)
+        .iter()
+        .map(|day| match day.parse::<u32>() {
+            Ok(n) if n < 7 => Ok(n),
+            Ok(_) => Err(format!("day {} is not in 0-6", day)),
+            Err(_) => DAY_NAMES
+                .iter()
+                .position(|name| day.to_lowercase().starts_with(name))
+                .map(|n| n as u32)
+                .ok_or(format!("\"{}\" is not a day of week", day)),
+        })
+        .collect()
+}
+
+/// "HH:MM" or "HH:MM:SS"
+pub fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+        .map_err(|_| format!("\"{}\" is not a time (HH:MM)", value))
+}
+
+/// "YYYY-MM-DD" or an Org timestamp such as "<2022-07-25 Mon>"
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    let date = value.trim_start_matches(['<', '[']);
+    date.get(..10)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .ok_or(format!("\"{}\" is not a date (YYYY-MM-DD)", value))
+}
+
+fn parse_count(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("\"{}\" is not a positive number", value)),
+    }
+}
+
+/// Dates, with or without a time, as "YYYY-MM-DD" or Org timestamps:
+/// "2022-08-01, <2022-08-15 Mon 17:00>"
+pub fn parse_dates(value: &str) -> Result<Vec<FCStart>, String> {
+    let mut dates: Vec<FCStart> = Vec::new();
+    let tokens = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|token| token.trim_matches(|c| c == '<' || c == '>' || c == '[' || c == ']'))
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        if let Ok(date) = NaiveDate::parse_from_str(token, "%Y-%m-%d") {
+            dates.push(FCStart::Day(date));
+        } else if let Ok(time) = parse_time(token) {
+            match dates.last_mut() {
+                Some(last @ FCStart::Day(..)) => {
+                    *last = FCStart::DateTime(NaiveDateTime::new(
+                        last.to_naive_date_time().date(),
+                        time,
+                    ));
+                }
+                _ => return Err(format!("time {} without a date", token)),
+            }
+        } else if !DAY_NAMES
+            .iter()
+            .any(|name| token.to_lowercase().starts_with(name))
+        {
+            return Err(format!("\"{}\" is not a date (YYYY-MM-DD)", token));
+        }
+    }
+    if dates.is_empty() {
+        return Err("no dates".to_string());
+    }
+    Ok(dates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_valid_values() {
+        let mut overrides = Overrides::default();
+        overrides.set("FC_ALLDAY", "t").unwrap();
+        overrides.set("FC_DISPLAY", "background").unwrap();
+        overrides.set("FC_DAYS_OF_WEEK", "Mon, wed 5").unwrap();
+        overrides.set("FC_END_RECUR", "<2022-09-30 Fri>").unwrap();
+        overrides.set("FC_BORDER_COLOR", "Dark Orange").unwrap();
+        overrides.set("FC_BG_COLOR", "rgb(0, 0, 255)").unwrap();
+        assert_eq!(overrides.all_day, Some(true));
+        assert_eq!(overrides.display.as_deref(), Some("background"));
+        assert_eq!(overrides.days_of_week, Some(vec![1, 3, 5]));
+        assert_eq!(overrides.end_recur, NaiveDate::from_ymd_opt(2022, 9, 30));
+        assert_eq!(overrides.border_color.as_deref(), Some("#ff8c00"));
+    }
+
+    #[test]
+    fn accept_urls_and_paths() {
+        for url in [
+            "https://example.com:8080/path",
+            "mailto:me@example.com",
+            "TEL:+331234",
+            "/agenda",
+            "#today",
+        ] {
+            assert_eq!(parse_url(url).as_deref(), Ok(url));
+        }
+    }
+
+    #[test]
+    fn parse_exception_dates() {
+        let date = |d| NaiveDate::from_ymd_opt(2022, 8, d).unwrap();
+        assert_eq!(
+            parse_dates("2022-08-01, <2022-08-15 Mon 17:00> [2022-08-29 Mon]"),
+            Ok(vec![
+                FCStart::Day(date(1)),
+                FCStart::DateTime(date(15).and_hms_opt(17, 0, 0).unwrap()),
+                FCStart::Day(date(29)),
+            ])
+        );
+    }
+
+    #[test]
+    fn reject_invalid_values() {
+        let mut overrides = Overrides::default();
+        assert!(overrides.set("FC_ALLDAY", "maybe").is_err());
+        assert!(overrides.set("FC_DISPLAY", "hidden").is_err());
+        assert!(overrides.set("FC_URL", "example.com").is_err());
+        assert!(overrides.set("FC_URL", "example.com:8080/path").is_err());
+        assert!(overrides.set("FC_URL", "localhost:3000").is_err());
+        assert!(overrides.set("FC_URL", "javascript://%0aalert(1)").is_err());
+        assert!(overrides
+            .set("FC_URL", "file:///home/me/notes.org")
+            .is_err());
+        assert!(overrides.set("FC_CLASS", "a.b").is_err());
+        assert!(overrides.set("FC_DAYS_OF_WEEK", "7").is_err());
+        assert!(overrides.set("FC_START_TIME", "25:00").is_err());
+        assert!(overrides.set("FC_BG_COLOUR", "red").is_err());
+        assert!(overrides.set("FC_BG_COLOR", "gren").is_err());
+        assert!(overrides.set("FC_BACKGROUND_COLOR", "#12345").is_err());
+        assert!(overrides
+            .set("FC_EXDATE", "2022-08-01 12:00:00:00")
+            .is_err());
+        assert!(overrides.set("FC_COUNT", "0").is_err());
+        assert_eq!(overrides, Overrides::default());
+    }
+}
@@ -0,0 +1,933 @@
+//! Queries selecting the headlines which become events, in the spirit of
+//! org-ql. A query is written either as an S-expression:
+//!
+//! ```text
+//! (and (todo "NEXT") (tags "client") (scheduled :from -7 :to 14))
+//! ```
+//!
+//! or in a plain syntax, where terms next to each other must all match:
+//!
+//! ```text
+//! todo:NEXT tags:client scheduled:-7..14
+//! (tags:work or tags:home) !done:
+//! ```
+//!
+//! | S-expression                         | Plain                           | Matches                              |
+//! |--------------------------------------|---------------------------------|--------------------------------------|
+//! | `(and Q ...)`, `(or Q ...)`          | `Q Q`, `Q and Q`, `Q or Q`      |                                      |
+//! | `(not Q)`                            | `not Q`, `!Q`                   |                                      |
+//! | `(todo)`, `(todo "A" "B")`           | `todo:`, `todo:A,B`             | any TODO keyword, or one of these    |
+//! | `(done)`                             | `done:`                         | a done keyword                       |
+//! | `(tags "a" "b")`                     | `tags:a,b`                      | one of the tags, inherited included  |
+//! | `(tags-local "a")`                   | `tags-local:a`                  | one of the headline's own tags       |
+//! | `(priority "A")`, `(priority >= "B")`| `priority:A`, `priority:>=B`    | priority, `A` is the highest         |
+//! | `(property "K")`, `(property "K" "v")`| `property:K`, `property:K=v`   | property set, or set to the value    |
+//! | `(level 2)`, `(level 1 3)`           | `level:2`, `level:1..3`         | headline level                       |
+//! | `(heading "a")`                      | `heading:a`, `a`                | words in the title (ignoring case)   |
+//! | `(outline-path "a")`                 | `outline-path:a`                | words in an ancestor's title or its own |
+//! | `(scheduled :from -7 :to 14)`        | `scheduled:-7..14`, `scheduled:from=-7,to=14` | SCHEDULED in the range |
+//! | `(deadline :on today)`               | `deadline:today`                | DEADLINE on the day                  |
+//!
+//! The date predicates are `scheduled`, `deadline`, `closed`, `clocked` (a
+//! CLOCK line), `planning` (one of the first three) and `ts` (any of them).
+//! Without a range they match when the timestamp is there. Days are a
+//! number of days from today, `today` or `YYYY-MM-DD`.
+//!
+//! A query is an S-expression when it starts with `(` and a predicate or
+//! `and`, `or`, `not`. In the plain syntax a word which is not a known
+//! predicate is searched in the title, so `10:00` is not an error.
+use crate::outline::HeadlineContext;
+use chrono::{Duration, Local, NaiveDate};
+use orgize::elements;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of days from today a date bound can be, a hundred years
+const MAX_DAYS: i64 = 36_525;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Query {
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    /// One of the keywords, any keyword when empty
+    Todo(Vec<String>),
+    Done,
+    /// One of the effective tags, any tag when empty
+    Tags(Vec<String>),
+    /// One of the headline's own tags, any tag when empty
+    LocalTags(Vec<String>),
+    /// One of the priorities, any priority when empty
+    Priority(Vec<char>),
+    PriorityCompare(Compare, char),
+    Property(String, Option<String>),
+    /// Lowest and deepest level
+    Level(usize, usize),
+    Heading(Vec<String>),
+    OutlinePath(Vec<String>),
+    Date(DateField, DateRange),
+}
+
+/// Comparison of priorities: `>= B` is `A` or `B`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Lower,
+    LowerOrEqual,
+    Equal,
+    HigherOrEqual,
+    Higher,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Scheduled,
+    Deadline,
+    Closed,
+    Clocked,
+    Planning,
+    Ts,
+}
+
+/// Days from `from` to `to`, both included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<DateBound>,
+    pub to: Option<DateBound>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateBound {
+    /// Days from today
+    Days(i64),
+    Date(NaiveDate),
+}
+
+impl Query {
+    /// Does the headline match, today being the local date?
+    pub fn matches(&self, headline: &HeadlineContext) -> bool {
+        self.matches_on(headline, Local::now().naive_local().date())
+    }
+
+    pub fn matches_on(&self, headline: &HeadlineContext, today: NaiveDate) -> bool {
+        let title = headline.title.as_ref();
+        match self {
+            Query::And(queries) => queries.iter().all(|q| q.matches_on(headline, today)),
+            Query::Or(queries) => queries.iter().any(|q| q.matches_on(headline, today)),
+            Query::Not(query) => !query.matches_on(headline, today),
+            Query::Todo(keywords) => title.keyword.as_ref().is_some_and(|keyword| {
+                keywords.is_empty() || keywords.iter().any(|k| k == keyword)
+            }),
+            Query::Done => headline.done,
+            Query::Tags(tags) => any_of(tags, headline.tags.iter().map(|tag| tag.as_str())),
+            Query::LocalTags(tags) => any_of(tags, title.tags.iter().map(|tag| tag.as_ref())),
+            Query::Priority(priorities) => title
+                .priority
+                .is_some_and(|priority| priorities.is_empty() || priorities.contains(&priority)),
+            Query::PriorityCompare(compare, reference) => title
+                .priority
+                .is_some_and(|priority| compare.matches(priority, *reference)),
+            Query::Property(key, value) => {
+                let found = title
+                    .properties
+                    .clone()
+                    .into_iter()
+                    .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v.trim().to_string())
+                    .last();
+                match (found, value) {
+                    (Some(found), Some(value)) => found == *value,
+                    (found, None) => found.is_some(),
+                    (None, _) => false,
+                }
+            }
+            Query::Level(min, max) => (*min..=*max).contains(&title.level),
+            Query::Heading(words) => contains_all(&title.raw, words),
+            Query::OutlinePath(words) => words.iter().all(|word| {
+                headline
+                    .outline_path
+                    .iter()
+                    .any(|title| contains_all(title, std::slice::from_ref(word)))
+            }),
+            Query::Date(field, range) => dates(headline, *field)
+                .into_iter()
+                .any(|date| range.contains(date, today)),
+        }
+    }
+
+    /// Parse the value of an HTTP query parameter, still percent-encoded
+    pub fn from_query_param(value: &str) -> Result<Query, String> {
+        percent_decode(value)?.parse()
+    }
+}
+
+impl Compare {
+    fn matches(&self, priority: char, reference: char) -> bool {
+        // `A` is the highest priority
+        match self {
+            Compare::Lower => priority > reference,
+            Compare::LowerOrEqual => priority >= reference,
+            Compare::Equal => priority == reference,
+            Compare::HigherOrEqual => priority <= reference,
+            Compare::Higher => priority < reference,
+        }
+    }
+
+    fn parse(value: &str) -> Option<Compare> {
+        match value {
+            "<" => Some(Compare::Lower),
+            "<=" => Some(Compare::LowerOrEqual),
+            "=" => Some(Compare::Equal),
+            ">=" => Some(Compare::HigherOrEqual),
+            ">" => Some(Compare::Higher),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Compare::Lower => "<",
+            Compare::LowerOrEqual => "<=",
+            Compare::Equal => "=",
+            Compare::HigherOrEqual => ">=",
+            Compare::Higher => ">",
+        }
+    }
+}
+
+impl DateField {
+    fn parse(name: &str) -> Option<DateField> {
+        match name {
+            "scheduled" => Some(DateField::Scheduled),
+            "deadline" => Some(DateField::Deadline),
+            "closed" => Some(DateField::Closed),
+            "clocked" => Some(DateField::Clocked),
+            "planning" => Some(DateField::Planning),
+            "ts" => Some(DateField::Ts),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            DateField::Scheduled => "scheduled",
+            DateField::Deadline => "deadline",
+            DateField::Closed => "closed",
+            DateField::Clocked => "clocked",
+            DateField::Planning => "planning",
+            DateField::Ts => "ts",
+        }
+    }
+}
+
+impl DateRange {
+    fn contains(&self, date: NaiveDate, today: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from.date(today))
+            && self.to.is_none_or(|to| date <= to.date(today))
+    }
+}
+
+impl DateBound {
+    fn date(&self, today: NaiveDate) -> NaiveDate {
+        match self {
+            DateBound::Days(days) => {
+                let days = (*days).clamp(-MAX_DAYS, MAX_DAYS);
+                today
+                    .checked_add_signed(Duration::days(days))
+                    .unwrap_or(if days < 0 {
+                        NaiveDate::MIN
+                    } else {
+                        NaiveDate::MAX
+                    })
+            }
+            DateBound::Date(date) => *date,
+        }
+    }
+}
+
+impl FromStr for DateBound {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<DateBound, String> {
+        if value == "today" {
+            return Ok(DateBound::Days(0));
+        }
+        if let Ok(days) = value.parse::<i64>() {
+            if !(-MAX_DAYS..=MAX_DAYS).contains(&days) {
+                return Err(format!(
+                    "{} days is too far from today, at most {}",
+                    days, MAX_DAYS
+                ));
+            }
+            return Ok(DateBound::Days(days));
+        }
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(DateBound::Date)
+            .map_err(|_| {
+                format!(
+                    "\"{}\" is neither a number of days, today nor a date",
+                    value
+                )
+            })
+    }
+}
+
+impl fmt::Display for DateBound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateBound::Days(days) => write!(f, "{}", days),
+            DateBound::Date(date) => write!(f, "\"{}\"", date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+fn any_of<'t>(wanted: &[String], mut tags: impl Iterator<Item = &'t str>) -> bool {
+    if wanted.is_empty() {
+        return tags.next().is_some();
+    }
+    tags.any(|tag| wanted.iter().any(|w| w == tag))
+}
+
+fn contains_all(text: &str, words: &[String]) -> bool {
+    let text = text.to_lowercase();
+    words.iter().all(|word| text.contains(&word.to_lowercase()))
+}
+
+fn timestamp_date(timestamp: &Option<elements::Timestamp>) -> Option<NaiveDate> {
+    match timestamp.as_ref()? {
+        elements::Timestamp::Active { start, .. }
+        | elements::Timestamp::Inactive { start, .. }
+        | elements::Timestamp::ActiveRange { start, .. }
+        | elements::Timestamp::InactiveRange { start, .. } => Some(start.into()),
+        _ => None,
+    }
+}
+
+/// Days of the timestamps a date predicate looks at
+fn dates(headline: &HeadlineContext, field: DateField) -> Vec<NaiveDate> {
+    let planning = headline.title.planning.as_deref();
+    let scheduled = || planning.and_then(|p| timestamp_date(&p.scheduled));
+    let deadline = || planning.and_then(|p| timestamp_date(&p.deadline));
+    let closed = || planning.and_then(|p| timestamp_date(&p.closed));
+    let clocked = || -> Vec<NaiveDate> {
+        headline
+            .clocks
+            .iter()
+            .map(|(start, _)| (*start).into())
+            .collect()
+    };
+    match field {
+        DateField::Scheduled => scheduled().into_iter().collect(),
+        DateField::Deadline => deadline().into_iter().collect(),
+        DateField::Closed => closed().into_iter().collect(),
+        DateField::Clocked => clocked(),
+        DateField::Planning => [scheduled(), deadline(), closed()]
+            .into_iter()
+            .flatten()
+            .collect(),
+        DateField::Ts => [scheduled(), deadline(), closed()]
+            .into_iter()
+            .flatten()
+            .chain(clocked())
+            .collect(),
+    }
+}
+
+/// Decode `%XX` and `+` of a URL query string
+fn percent_decode(value: &str) -> Result<String, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut rest = value.bytes();
+    while let Some(byte) = rest.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = rest.by_ref().take(2).collect();
+                let hex = std::str::from_utf8(&hex).unwrap_or_default();
+                let byte = u8::from_str_radix(hex, 16)
+                    .map_err(|_| format!("bad percent-encoding \"%{}\"", hex))?;
+                bytes.push(byte);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| "percent-encoded text is not UTF-8".to_string())
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl fmt::Display for Query {
+    /// The S-expression of the query
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter, name: &str, items: &[String]) -> fmt::Result {
+            write!(f, "({}", name)?;
+            for item in items {
+                write!(f, " {}", quote(item))?;
+            }
+            write!(f, ")")
+        }
+        match self {
+            Query::And(queries) | Query::Or(queries) => {
+                let name = if let Query::And(..) = self {
+                    "and"
+                } else {
+                    "or"
+                };
+                write!(f, "({}", name)?;
+                for query in queries {
+                    write!(f, " {}", query)?;
+                }
+                write!(f, ")")
+            }
+            Query::Not(query) => write!(f, "(not {})", query),
+            Query::Todo(keywords) => list(f, "todo", keywords),
+            Query::Done => write!(f, "(done)"),
+            Query::Tags(tags) => list(f, "tags", tags),
+            Query::LocalTags(tags) => list(f, "tags-local", tags),
+            Query::Priority(priorities) => {
+                let priorities: Vec<String> = priorities.iter().map(|p| p.to_string()).collect();
+                list(f, "priority", &priorities)
+            }
+            Query::PriorityCompare(compare, priority) => {
+                write!(
+                    f,
+                    "(priority {} {})",
+                    compare.as_str(),
+                    quote(&priority.to_string())
+                )
+            }
+            Query::Property(key, None) => write!(f, "(property {})", quote(key)),
+            Query::Property(key, Some(value)) => {
+                write!(f, "(property {} {})", quote(key), quote(value))
+            }
+            Query::Level(min, max) if min == max => write!(f, "(level {})", min),
+            Query::Level(min, max) => write!(f, "(level {} {})", min, max),
+            Query::Heading(words) => list(f, "heading", words),
+            Query::OutlinePath(words) => list(f, "outline-path", words),
+            Query::Date(field, range) => {
+                write!(f, "({}", field.as_str())?;
+                if let Some(from) = range.from {
+                    write!(f, " :from {}", from)?;
+                }
+                if let Some(to) = range.to {
+                    write!(f, " :to {}", to)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl From<Query> for String {
+    fn from(query: Query) -> String {
+        query.to_string()
+    }
+}
+
+impl TryFrom<String> for Query {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Query, String> {
+        value.parse()
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    /// Parse an S-expression when the query starts with `(` followed by a
+    /// predicate or an operator, the plain syntax otherwise
+    fn from_str(value: &str) -> Result<Query, String> {
+        let tokens = tokenize(value)?;
+        let sexp = match tokens.as_slice() {
+            [Token::Open, Token::Word {
+                text,
+                quoted: false,
+            }, ..] => matches!(text.as_str(), "and" | "or" | "not") || is_predicate(text),
+            _ => false,
+        };
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let query = if sexp {
+            let mut queries = Vec::new();
+            while parser.peek().is_some() {
+                queries.push(parser.sexp_query()?);
+            }
+            and(queries)
+        } else {
+            parser.plain_or()?
+        };
+        match parser.peek() {
+            None => Ok(query),
+            Some(token) => Err(format!("unexpected {}", token)),
+        }
+    }
+}
+
+fn and(mut queries: Vec<Query>) -> Query {
+    if queries.len() == 1 {
+        queries.remove(0)
+    } else {
+        Query::And(queries)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    /// An atom, or a string when quoted
+    Word {
+        text: String,
+        quoted: bool,
+    },
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "\"(\""),
+            Token::Close => write!(f, "\")\""),
+            Token::Word { text, .. } => write!(f, "\"{}\"", text),
+        }
+    }
+}
+
+/// Split into parentheses and words. A quoted part belongs to the word it
+/// is in, so `heading:"weekly review"` is one word.
+fn tokenize(value: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = value.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+        } else {
+            let mut text = String::new();
+            let mut quoted = false;
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                chars.next();
+                if c != '"' {
+                    text.push(c);
+                    continue;
+                }
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => text.push(escaped),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(c) => text.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+            }
+            tokens.push(Token::Word { text, quoted });
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect_close(&mut self) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Close) => Ok(()),
+            Some(token) => Err(format!("expected \")\", got {}", token)),
+            None => Err("missing \")\"".to_string()),
+        }
+    }
+
+    /// `(name args ...)`
+    fn sexp_query(&mut self) -> Result<Query, String> {
+        match self.next() {
+            Some(Token::Open) => {}
+            Some(token) => return Err(format!("expected \"(\", got {}", token)),
+            None => return Err("empty query".to_string()),
+        }
+        let name = match self.next() {
+            Some(Token::Word {
+                text,
+                quoted: false,
+            }) => text,
+            Some(token) => return Err(format!("expected a predicate, got {}", token)),
+            None => return Err("missing \")\"".to_string()),
+        };
+
+        if matches!(name.as_str(), "and" | "or" | "not") {
+            let mut queries = Vec::new();
+            while let Some(Token::Open) = self.peek() {
+                queries.push(self.sexp_query()?);
+            }
+            self.expect_close()?;
+            return match name.as_str() {
+                "and" => Ok(Query::And(queries)),
+                "or" => Ok(Query::Or(queries)),
+                _ if queries.len() == 1 => Ok(Query::Not(Box::new(queries.remove(0)))),
+                _ => Err("not takes one query".to_string()),
+            };
+        }
+
+        let mut args: Vec<String> = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Close) => break,
+                // Elisp quotes symbols: (priority '>= "B")
+                Some(Token::Word { text, .. }) => {
+                    args.push(text.strip_prefix('\'').unwrap_or(&text).to_string())
+                }
+                Some(Token::Open) => return Err(format!("unexpected \"(\" in ({})", name)),
+                None => return Err("missing \")\"".to_string()),
+            }
+        }
+        predicate(&name, &args, Syntax::Sexp)
+    }
+
+    /// Terms separated by `or`
+    fn plain_or(&mut self) -> Result<Query, String> {
+        let mut queries = vec![self.plain_and()?];
+        while self.is_operator("or") {
+            self.next();
+            queries.push(self.plain_and()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Query::Or(queries)
+        })
+    }
+
+    /// Terms next to each other or separated by `and`
+    fn plain_and(&mut self) -> Result<Query, String> {
+        let mut queries = vec![self.plain_unary()?];
+        loop {
+            if self.is_operator("and") {
+                self.next();
+            } else if self.is_operator("or") || matches!(self.peek(), None | Some(Token::Close)) {
+                break;
+            }
+            queries.push(self.plain_unary()?);
+        }
+        Ok(and(queries))
+    }
+
+    fn plain_unary(&mut self) -> Result<Query, String> {
+        if self.is_operator("not") || self.is_operator("!") {
+            self.next();
+            return Ok(Query::Not(Box::new(self.plain_unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let query = self.plain_or()?;
+                self.expect_close()?;
+                Ok(query)
+            }
+            Some(Token::Word { text, quoted }) => {
+                if !quoted && text.len() > 1 && text.starts_with('!') {
+                    return Ok(Query::Not(Box::new(plain_term(&text[1..], quoted)?)));
+                }
+                plain_term(&text, quoted)
+            }
+            Some(Token::Close) => Err("unexpected \")\"".to_string()),
+            None => Err("missing a term".to_string()),
+        }
+    }
+
+    fn is_operator(&self, operator: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word { text, quoted: false }) if text.eq_ignore_ascii_case(operator))
+    }
+}
+
+#[derive(PartialEq)]
+enum Syntax {
+    Sexp,
+    Plain,
+}
+
+/// `name:arg,arg`, or words of the title
+fn plain_term(text: &str, quoted: bool) -> Result<Query, String> {
+    if !quoted {
+        if let Some((name, args)) = text.split_once(':') {
+            if is_predicate(name) {
+                let args: Vec<String> = args
+                    .split(',')
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| arg.to_string())
+                    .collect();
+                return predicate(name, &args, Syntax::Plain);
+            }
+        }
+    }
+    Ok(Query::Heading(vec![text.to_string()]))
+}
+
+fn is_predicate(name: &str) -> bool {
+    matches!(
+        name,
+        "todo"
+            | "done"
+            | "tags"
+            | "tags-local"
+            | "priority"
+            | "property"
+            | "level"
+            | "heading"
+            | "outline-path"
+    ) || DateField::parse(name).is_some()
+}
+
+fn predicate(name: &str, args: &[String], syntax: Syntax) -> Result<Query, String> {
+    let strings = || args.to_vec();
+    match name {
+        "todo" => Ok(Query::Todo(strings())),
+        "done" if args.is_empty() => Ok(Query::Done),
+        "done" => Err("done takes no argument".to_string()),
+        "tags" => Ok(Query::Tags(strings())),
+        "tags-local" => Ok(Query::LocalTags(strings())),
+        "priority" => parse_priority(args),
+        "property" => match (syntax, args) {
+            (Syntax::Plain, [arg]) => Ok(match arg.split_once('=') {
+                Some((key, value)) => Query::Property(key.to_string(), Some(value.to_string())),
+                None => Query::Property(arg.to_string(), None),
+            }),
+            (Syntax::Sexp, [key]) => Ok(Query::Property(key.to_string(), None)),
+            (Syntax::Sexp, [key, value]) => {
+                Ok(Query::Property(key.to_string(), Some(value.to_string())))
+            }
+            _ => Err("property takes a key and an optional value".to_string()),
+        },
+        "level" => parse_level(args, syntax),
+        "heading" => Ok(Query::Heading(strings())),
+        "outline-path" => Ok(Query::OutlinePath(strings())),
+        _ => match DateField::parse(name) {
+            Some(field) => Ok(Query::Date(field, parse_range(args, syntax)?)),
+            None => Err(format!("unknown predicate \"{}\"", name)),
+        },
+    }
+}
+
+fn parse_priority_letter(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Ok(c.to_ascii_uppercase()),
+        _ => Err(format!("\"{}\" is not a priority", value)),
+    }
+}
+
+/// `A B`, `>= B` or `>=B`
+fn parse_priority(args: &[String]) -> Result<Query, String> {
+    if let [compare, priority] = args {
+        if let Some(compare) = Compare::parse(compare) {
+            return Ok(Query::PriorityCompare(
+                compare,
+                parse_priority_letter(priority)?,
+            ));
+        }
+    }
+    if let [arg] = args {
+        let split = arg.trim_end_matches(|c: char| c.is_ascii_alphanumeric());
+        if let Some(compare) = Compare::parse(split) {
+            let priority = parse_priority_letter(&arg[split.len()..])?;
+            return Ok(Query::PriorityCompare(compare, priority));
+        }
+    }
+    let priorities = args
+        .iter()
+        .map(|arg| parse_priority_letter(arg))
+        .collect::<Result<Vec<char>, String>>()?;
+    Ok(Query::Priority(priorities))
+}
+
+fn parse_level_number(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a level", value))
+}
+
+/// `2` or `1 3` (`1..3` in the plain syntax)
+fn parse_level(args: &[String], syntax: Syntax) -> Result<Query, String> {
+    let (min, max) = match (syntax, args) {
+        (Syntax::Plain, [arg]) => match arg.split_once("..") {
+            Some((min, max)) => (parse_level_number(min)?, parse_level_number(max)?),
+            None => (parse_level_number(arg)?, parse_level_number(arg)?),
+        },
+        (Syntax::Sexp, [level]) => (parse_level_number(level)?, parse_level_number(level)?),
+        (Syntax::Sexp, [min, max]) => (parse_level_number(min)?, parse_level_number(max)?),
+        _ => return Err("level takes a level or a range of levels".to_string()),
+    };
+    Ok(Query::Level(min, max))
+}
+
+/// `:from X :to Y :on X`, or in the plain syntax `from=X,to=Y`, `X..Y` and `X`
+fn parse_range(args: &[String], syntax: Syntax) -> Result<DateRange, String> {
+    let mut range = DateRange::default();
+    let mut set = |key: &str, value: &str| -> Result<(), String> {
+        let bound = Some(value.parse()?);
+        match key {
+            "from" => range.from = bound,
+            "to" => range.to = bound,
+            "on" => {
+                range.from = bound;
+                range.to = bound;
+            }
+            _ => return Err(format!("unknown date argument \"{}\"", key)),
+        }
+        Ok(())
+    };
+    match syntax {
+        Syntax::Sexp => {
+            for pair in args.chunks(2) {
+                match pair {
+                    [key, value] if key.starts_with(':') => set(&key[1..], value)?,
+                    _ => return Err(format!("expected :from, :to or :on, got \"{}\"", pair[0])),
+                }
+            }
+        }
+        Syntax::Plain => {
+            for arg in args {
+                if let Some((key, value)) = arg.split_once('=') {
+                    set(key, value)?;
+                } else if let Some((from, to)) = arg.split_once("..") {
+                    if !from.is_empty() {
+                        set("from", from)?;
+                    }
+                    if !to.is_empty() {
+                        set("to", to)?;
+                    }
+                } else {
+                    set("on", arg)?;
+                }
+            }
+        }
+    }
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_sexp_and_plain_syntax() {
+        let expected = Query::And(vec![
+            Query::Todo(strings(&["NEXT"])),
+            Query::Tags(strings(&["client"])),
+            Query::Date(
+                DateField::Scheduled,
+                DateRange {
+                    from: Some(DateBound::Days(-7)),
+                    to: Some(DateBound::Days(14)),
+                },
+            ),
+        ]);
+        let sexp: Query = r#"(and (todo "NEXT") (tags "client") (scheduled :from -7 :to 14))"#
+            .parse()
+            .unwrap();
+        assert_eq!(sexp, expected);
+        let plain: Query = "todo:NEXT and tags:client scheduled:-7..14"
+            .parse()
+            .unwrap();
+        assert_eq!(plain, expected);
+        assert_eq!(sexp.to_string().parse::<Query>().unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_plain_operators() {
+        let query: Query = r#"(tags:work or tags:home) !done: "10:00 call""#.parse().unwrap();
+        assert_eq!(
+            query,
+            Query::And(vec![
+                Query::Or(vec![
+                    Query::Tags(strings(&["work"])),
+                    Query::Tags(strings(&["home"]))
+                ]),
+                Query::Not(Box::new(Query::Done)),
+                Query::Heading(strings(&["10:00 call"])),
+            ])
+        );
+        assert_eq!(
+            "priority:>=B property:CLIENT=acme level:1..2".parse::<Query>(),
+            Ok(Query::And(vec![
+                Query::PriorityCompare(Compare::HigherOrEqual, 'B'),
+                Query::Property("CLIENT".to_string(), Some("acme".to_string())),
+                Query::Level(1, 2),
+            ]))
+        );
+        assert_eq!(
+            r#"(priority '>= "B")"#.parse::<Query>(),
+            Ok(Query::PriorityCompare(Compare::HigherOrEqual, 'B'))
+        );
+    }
+
+    #[test]
+    fn parse_query_param() {
+        assert_eq!(
+            Query::from_query_param("todo%3ANEXT+tags%3Aclient"),
+            "todo:NEXT tags:client".parse()
+        );
+    }
+
+    #[test]
+    fn reject_invalid_queries() {
+        assert!("(and (todo \"NEXT\")".parse::<Query>().is_err());
+        assert!("(and (frobnicate))".parse::<Query>().is_err());
+        assert!("(scheduled :from yesterday)".parse::<Query>().is_err());
+        assert!("level:x".parse::<Query>().is_err());
+        assert!("scheduled:1000000000".parse::<Query>().is_err());
+        assert!(Query::from_query_param("deadline%3A-99999999999").is_err());
+        assert!("tags:work or".parse::<Query>().is_err());
+        assert!("\"unterminated".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn keep_far_dates_in_range() {
+        let today = NaiveDate::from_ymd_opt(2022, 8, 1).unwrap();
+        assert_eq!(
+            DateBound::Days(14).date(today),
+            NaiveDate::from_ymd_opt(2022, 8, 15).unwrap()
+        );
+        let near_end = NaiveDate::MAX - Duration::days(1);
+        assert_eq!(DateBound::Days(i64::MAX).date(near_end), NaiveDate::MAX);
+        assert_eq!(
+            DateBound::Days(i64::MIN).date(today),
+            today - Duration::days(MAX_DAYS)
+        );
+    }
+
+    #[test]
+    fn compare_priorities() {
+        assert!(Compare::HigherOrEqual.matches('A', 'B'));
+        assert!(Compare::HigherOrEqual.matches('B', 'B'));
+        assert!(!Compare::HigherOrEqual.matches('C', 'B'));
+        assert!(Compare::Lower.matches('C', 'B'));
+    }
+}
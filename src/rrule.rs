@@ -0,0 +1,715 @@
+//! RFC 5545 recurrence rules given in the `FC_RRULE` property, for what
+//! Org repeaters ("every N units") cannot express, e.g.
+//!
+//! ```text
+//! :FC_RRULE: FREQ=MONTHLY;BYDAY=2TH,4TH       every 2nd and 4th Thursday
+//! :FC_RRULE: FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1
+//!                                             last weekday of the month
+//! ```
+//!
+//! DTSTART is not part of the property: the SCHEDULED (or DEADLINE)
+//! timestamp supplies the start and, with a time range, the duration.
+//!
+//! Repeating events can also be expanded here into one event per
+//! occurrence, for clients without FullCalendar's rrule plugin, with the
+//! `expand_recurring` option.
+use crate::diagnostic::Diagnostic;
+use crate::fullcalendar::{occurrence_id, FCEvent, FCRRule, FCStart};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Periods looked at before giving up on a rule which never matches
+const MAX_PERIODS: i64 = 100_000;
+
+const FREQUENCIES: &[&str] = &[
+    "YEARLY", "MONTHLY", "WEEKLY", "DAILY", "HOURLY", "MINUTELY", "SECONDLY",
+];
+
+const WEEKDAYS: &[&str] = &["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+/// Which occurrences of the repeating events are expanded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExpandOptions {
+    /// Days before today of the first occurrences
+    pub days_before: i64,
+    /// Days after today of the last occurrences
+    pub days_after: i64,
+}
+
+impl Default for ExpandOptions {
+    fn default() -> ExpandOptions {
+        ExpandOptions {
+            days_before: 30,
+            days_after: 365,
+        }
+    }
+}
+
+impl ExpandOptions {
+    /// `from..to` of the occurrences around a day
+    pub fn window(&self, today: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+        let midnight = today.and_hms_opt(0, 0, 0).unwrap();
+        (
+            midnight - Duration::days(self.days_before),
+            midnight + Duration::days(self.days_after + 1),
+        )
+    }
+}
+
+/// A recurrence rule without DTSTART
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    /// Lower case, as FullCalendar's rrule plugin takes it
+    pub freq: String,
+    pub interval: usize,
+    /// Lower case weekdays ("mo", "tu", ...)
+    pub byweekday: Vec<String>,
+    pub bymonthday: Vec<i32>,
+    pub bymonth: Vec<u32>,
+    pub bysetpos: Vec<i32>,
+    pub count: Option<u32>,
+    pub until: Option<FCStart>,
+    pub wkst: Option<String>,
+}
+
+impl RRule {
+    /// FullCalendar rrule starting at `dtstart`. A date UNTIL of a rule
+    /// with times becomes the end of that day, which the rrule plugin would
+    /// take for its midnight.
+    pub fn to_fc_rrule(&self, dtstart: FCStart) -> FCRRule {
+        let mut rrule = FCRRule::new(dtstart, &self.freq, self.interval);
+        rrule.byweekday = self.byweekday.clone();
+        rrule.bymonthday = self.bymonthday.clone();
+        rrule.bymonth = self.bymonth.clone();
+        rrule.bysetpos = self.bysetpos.clone();
+        rrule.count = self.count;
+        rrule.until = self.until.map(|until| match until {
+            FCStart::Day(date) => until_end_of(date, &dtstart),
+            until => until,
+        });
+        rrule.wkst = self.wkst.clone();
+        rrule
+    }
+}
+
+impl FromStr for RRule {
+    type Err = String;
+
+    /// Parse "FREQ=...;INTERVAL=...", with or without a leading "RRULE:"
+    fn from_str(value: &str) -> Result<RRule, String> {
+        let value = value.trim();
+        let value = value.strip_prefix("RRULE:").unwrap_or(value);
+
+        let mut freq: Option<String> = None;
+        let mut rrule = RRule {
+            freq: String::new(),
+            interval: 1,
+            byweekday: Vec::new(),
+            bymonthday: Vec::new(),
+            bymonth: Vec::new(),
+            bysetpos: Vec::new(),
+            count: None,
+            until: None,
+            wkst: None,
+        };
+        // BYDAY with ordinals, e.g. 2TH
+        let mut nth_weekdays: Vec<(i32, String)> = Vec::new();
+
+        for part in value.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or(format!("\"{}\" is not NAME=VALUE", part))?;
+            let key = key.to_uppercase();
+            let value = value.to_uppercase();
+            match key.as_str() {
+                "FREQ" => {
+                    if !FREQUENCIES.contains(&value.as_str()) {
+                        return Err(format!("unknown FREQ \"{}\"", value));
+                    }
+                    freq = Some(value.to_lowercase());
+                }
+                "INTERVAL" => {
+                    rrule.interval = parse_number(&key, &value, 1, i32::MAX)? as usize;
+                }
+                "COUNT" => rrule.count = Some(parse_number(&key, &value, 1, i32::MAX)? as u32),
+                "UNTIL" => rrule.until = Some(parse_until(&value)?),
+                "WKST" => rrule.wkst = Some(parse_weekday(&value)?),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        if !day.is_ascii() {
+                            return Err(format!("\"{}\" is not a weekday (MO, TU, ...)", day));
+                        }
+                        let split = day.len().saturating_sub(2);
+                        let weekday = parse_weekday(&day[split..])?;
+                        if split == 0 {
+                            rrule.byweekday.push(weekday);
+                        } else {
+                            let nth = parse_number(&key, &day[..split], -53, 53)?;
+                            nth_weekdays.push((nth, weekday));
+                        }
+                    }
+                }
+                "BYMONTHDAY" => rrule.bymonthday = parse_numbers(&key, &value, -31, 31)?,
+                "BYMONTH" => {
+                    rrule.bymonth = parse_numbers(&key, &value, 1, 12)?
+                        .into_iter()
+                        .map(|month| month as u32)
+                        .collect()
+                }
+                "BYSETPOS" => rrule.bysetpos = parse_numbers(&key, &value, -366, 366)?,
+                _ => return Err(format!("{} is not supported", key)),
+            }
+        }
+
+        rrule.freq = freq.ok_or("FREQ is missing")?;
+        if rrule.count.is_some() && rrule.until.is_some() {
+            return Err("COUNT and UNTIL cannot be used together".to_string());
+        }
+        if !nth_weekdays.is_empty() {
+            nth_weekdays_to_setpos(&mut rrule, nth_weekdays)?;
+        }
+        Ok(rrule)
+    }
+}
+
+/// The rrule plugin takes weekdays as plain strings, so "2TH,4TH" is sent as
+/// byweekday "th" with bysetpos 2, 4. That only means the same for a single
+/// weekday in a single month or year: BYSETPOS picks among all the days of
+/// the period, so "1MO,1FR" would be the first of the Mondays and Fridays,
+/// and among those left by BYMONTHDAY.
+fn nth_weekdays_to_setpos(
+    rrule: &mut RRule,
+    nth_weekdays: Vec<(i32, String)>,
+) -> Result<(), String> {
+    if !rrule.byweekday.is_empty() || !rrule.bysetpos.is_empty() || !rrule.bymonthday.is_empty() {
+        return Err(
+            "BYDAY with ordinals cannot be mixed with plain weekdays, BYMONTHDAY or BYSETPOS"
+                .to_string(),
+        );
+    }
+    if rrule.freq != "monthly" && rrule.freq != "yearly" {
+        return Err("BYDAY with ordinals needs FREQ=MONTHLY or FREQ=YEARLY".to_string());
+    }
+    if rrule.freq == "yearly" && rrule.bymonth.len() > 1 {
+        return Err("BYDAY with ordinals cannot be used with several BYMONTH".to_string());
+    }
+    let mut positions: Vec<i32> = Vec::new();
+    for (nth, weekday) in &nth_weekdays {
+        if *weekday != nth_weekdays[0].1 {
+            return Err(
+                "BYDAY with ordinals must be of a single weekday, e.g. 2TH,4TH".to_string(),
+            );
+        }
+        if !positions.contains(nth) {
+            positions.push(*nth);
+        }
+    }
+    rrule.byweekday = vec![nth_weekdays[0].1.clone()];
+    rrule.bysetpos = positions;
+    Ok(())
+}
+
+fn parse_weekday(value: &str) -> Result<String, String> {
+    if WEEKDAYS.contains(&value) {
+        return Ok(value.to_lowercase());
+    }
+    Err(format!("\"{}\" is not a weekday (MO, TU, ...)", value))
+}
+
+fn parse_number(key: &str, value: &str, min: i32, max: i32) -> Result<i32, String> {
+    match value.trim_start_matches('+').parse::<i32>() {
+        Ok(n) if n != 0 && min <= n && n <= max => Ok(n),
+        _ => Err(format!("{} \"{}\" is not in {}..{}", key, value, min, max)),
+    }
+}
+
+fn parse_numbers(key: &str, value: &str, min: i32, max: i32) -> Result<Vec<i32>, String> {
+    value
+        .split(',')
+        .map(|n| parse_number(key, n, min, max))
+        .collect()
+}
+
+/// "20221231" or "20221231T235959" (a trailing "Z" is ignored: Org times are local)
+fn parse_until(value: &str) -> Result<FCStart, String> {
+    let until = value.trim_end_matches('Z');
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(until, "%Y%m%dT%H%M%S") {
+        return Ok(FCStart::DateTime(datetime));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(until, "%Y%m%d") {
+        return Ok(FCStart::Day(date));
+    }
+    Err(format!(
+        "UNTIL \"{}\" is not YYYYMMDD or YYYYMMDDTHHMMSS",
+        value
+    ))
+}
+
+/// An exception date in the form of the rule's start: FullCalendar only
+/// skips an occurrence whose start is equal to the exception.
+pub fn align_exdate(date: FCStart, dtstart: &FCStart) -> FCStart {
+    match (date, dtstart) {
+        (FCStart::Day(date), FCStart::DateTime(dtstart)) => {
+            FCStart::DateTime(NaiveDateTime::new(date, dtstart.time()))
+        }
+        (FCStart::DateTime(datetime), FCStart::Day(..)) => FCStart::Day(datetime.date()),
+        (date, _) => date,
+    }
+}
+
+/// UNTIL which keeps the occurrences on the given day
+pub fn until_end_of(date: NaiveDate, dtstart: &FCStart) -> FCStart {
+    match dtstart {
+        FCStart::Day(..) => FCStart::Day(date),
+        FCStart::DateTime(..) => FCStart::DateTime(date.and_hms_opt(23, 59, 59).unwrap()),
+    }
+}
+
+/// Replace repeating events with one event per occurrence starting in
+/// `from..to`, leaving out the exception dates. Other events are kept as
+/// they are, and so are those whose occurrences cannot be listed, which are
+/// reported. Occurrences get the id of their series with the start appended.
+pub fn expand_recurring_events(
+    events: Vec<FCEvent>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> (Vec<FCEvent>, Vec<Diagnostic>) {
+    let mut expanded: Vec<FCEvent> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for event in events {
+        let rrule = match &event.rrule {
+            Some(rrule) => rrule.clone(),
+            None => {
+                expanded.push(event);
+                continue;
+            }
+        };
+        let starts = match occurrences(&rrule, from, to) {
+            Ok(starts) => starts,
+            Err(message) => {
+                diagnostics.push(Diagnostic::new(
+                    event.file_path.as_deref().unwrap_or_default(),
+                    &event.title,
+                    message,
+                ));
+                expanded.push(event);
+                continue;
+            }
+        };
+        let length = event
+            .end
+            .map(|end| end.signed_duration_since(event.start.to_naive_date_time()));
+        let exdate: Vec<NaiveDateTime> = event
+            .exdate
+            .iter()
+            .map(|date| date.to_naive_date_time())
+            .collect();
+
+        for start in starts {
+            if exdate.contains(&start) {
+                continue;
+            }
+            let mut occurrence = event.clone();
+            occurrence.start = match rrule.dtstart {
+                FCStart::Day(..) => FCStart::Day(start.date()),
+                FCStart::DateTime(..) => FCStart::DateTime(start),
+            };
+            occurrence.end = length.map(|length| start + length);
+            occurrence.rrule = None;
+            occurrence.exdate = Vec::new();
+            occurrence.duration = None;
+            occurrence.id = event
+                .id
+                .as_ref()
+                .map(|id| occurrence_id(id, &occurrence.start));
+            expanded.push(occurrence);
+        }
+    }
+    (expanded, diagnostics)
+}
+
+/// Starts of the occurrences of a rule in `from..to`.
+/// COUNT is counted from DTSTART, not from `from`. An error when
+/// [`MAX_PERIODS`] periods are not enough to reach `to`.
+pub fn occurrences(
+    rrule: &FCRRule,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<Vec<NaiveDateTime>, String> {
+    let dtstart = rrule.dtstart.to_naive_date_time();
+    let until = rrule.until.map(|until| match until {
+        FCStart::Day(date) => date.and_hms_opt(23, 59, 59).unwrap(),
+        FCStart::DateTime(datetime) => datetime,
+    });
+    let interval = rrule.interval.max(1) as i64;
+
+    let mut found: Vec<NaiveDateTime> = Vec::new();
+    let mut count: u32 = 0;
+    for period in 0..MAX_PERIODS {
+        let candidates = match period_candidates(rrule, dtstart, period * interval) {
+            Some(candidates) => candidates,
+            None => return Ok(found),
+        };
+        for start in candidates {
+            if start < dtstart {
+                continue;
+            }
+            if until.is_some_and(|until| start > until)
+                || rrule.count.is_some_and(|max| count >= max)
+                || start >= to
+            {
+                return Ok(found);
+            }
+            count += 1;
+            if start >= from {
+                found.push(start);
+            }
+        }
+    }
+    Err(format!(
+        "the occurrences are not expanded: {} {} periods do not reach the end of the window",
+        MAX_PERIODS, rrule.freq
+    ))
+}
+
+/// Occurrences in the n-th period (year, month, week ...) after DTSTART's,
+/// sorted and narrowed down by BYSETPOS. None when the date overflows.
+fn period_candidates(
+    rrule: &FCRRule,
+    dtstart: NaiveDateTime,
+    n: i64,
+) -> Option<Vec<NaiveDateTime>> {
+    let date = dtstart.date();
+    let time = dtstart.time();
+    let mut days: Vec<NaiveDate> = Vec::new();
+
+    match rrule.freq.as_str() {
+        "yearly" => {
+            let year = date.year().checked_add(i32::try_from(n).ok()?)?;
+            let months: Vec<u32> = if !rrule.bymonth.is_empty() {
+                rrule.bymonth.clone()
+            } else if !rrule.byweekday.is_empty() || !rrule.bymonthday.is_empty() {
+                (1..=12).collect()
+            } else {
+                vec![date.month()]
+            };
+            for month in months {
+                days.extend(days_in_period_month(rrule, year, month, date.day()));
+            }
+        }
+        "monthly" => {
+            let months = date.month0() as i64 + n;
+            let year = date
+                .year()
+                .checked_add(i32::try_from(months.div_euclid(12)).ok()?)?;
+            let month = months.rem_euclid(12) as u32 + 1;
+            days.extend(days_in_period_month(rrule, year, month, date.day()));
+        }
+        "weekly" => {
+            let wkst = rrule
+                .wkst
+                .as_deref()
+                .and_then(weekday_from_code)
+                .unwrap_or(Weekday::Mon);
+            let offset = (7 + date.weekday().num_days_from_monday() as i64
+                - wkst.num_days_from_monday() as i64)
+                % 7;
+            let week_start = date.checked_sub_signed(Duration::days(offset))?;
+            let week_start = week_start.checked_add_signed(Duration::days(n.checked_mul(7)?))?;
+            for i in 0..7 {
+                let day = week_start.checked_add_signed(Duration::days(i))?;
+                let weekday_matches = if rrule.byweekday.is_empty() {
+                    day.weekday() == date.weekday()
+                } else {
+                    matches_weekday(rrule, day)
+                };
+                if weekday_matches && matches_month(rrule, day) {
+                    days.push(day);
+                }
+            }
+        }
+        "daily" => {
+            let day = date.checked_add_signed(Duration::days(n))?;
+            if matches_weekday(rrule, day)
+                && matches_monthday(rrule, day)
+                && matches_month(rrule, day)
+            {
+                days.push(day);
+            }
+        }
+        _ => {
+            let step = match rrule.freq.as_str() {
+                "hourly" => Duration::hours(n),
+                "minutely" => Duration::minutes(n),
+                _ => Duration::seconds(n),
+            };
+            let start = dtstart.checked_add_signed(step)?;
+            let day = start.date();
+            if matches_weekday(rrule, day)
+                && matches_monthday(rrule, day)
+                && matches_month(rrule, day)
+            {
+                return Some(vec![start]);
+            }
+            return Some(Vec::new());
+        }
+    }
+
+    days.sort();
+    days.dedup();
+    let starts: Vec<NaiveDateTime> = days.into_iter().map(|day| day.and_time(time)).collect();
+    Some(select_positions(rrule, starts))
+}
+
+/// Days of a month matching BYMONTHDAY and BYDAY, or DTSTART's day without them
+fn days_in_period_month(rrule: &FCRRule, year: i32, month: u32, day: u32) -> Vec<NaiveDate> {
+    if rrule.byweekday.is_empty() && rrule.bymonthday.is_empty() {
+        return NaiveDate::from_ymd_opt(year, month, day)
+            .into_iter()
+            .collect();
+    }
+    (1..=31)
+        .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .filter(|day| matches_weekday(rrule, *day) && matches_monthday(rrule, *day))
+        .collect()
+}
+
+fn select_positions(rrule: &FCRRule, starts: Vec<NaiveDateTime>) -> Vec<NaiveDateTime> {
+    if rrule.bysetpos.is_empty() {
+        return starts;
+    }
+    let len = starts.len() as i32;
+    let mut selected: Vec<NaiveDateTime> = rrule
+        .bysetpos
+        .iter()
+        .filter_map(|pos| {
+            let index = if *pos > 0 { pos - 1 } else { len + pos };
+            if 0 <= index && index < len {
+                Some(starts[index as usize])
+            } else {
+                None
+            }
+        })
+        .collect();
+    selected.sort();
+    selected.dedup();
+    selected
+}
+
+fn matches_weekday(rrule: &FCRRule, day: NaiveDate) -> bool {
+    rrule.byweekday.is_empty()
+        || rrule
+            .byweekday
+            .iter()
+            .any(|code| weekday_from_code(code) == Some(day.weekday()))
+}
+
+fn matches_monthday(rrule: &FCRRule, day: NaiveDate) -> bool {
+    if rrule.bymonthday.is_empty() {
+        return true;
+    }
+    let days_in_month = (28..=31)
+        .rev()
+        .find(|last| NaiveDate::from_ymd_opt(day.year(), day.month(), *last).is_some())
+        .unwrap_or(28) as i32;
+    let from_start = day.day() as i32;
+    let from_end = from_start - days_in_month - 1;
+    rrule
+        .bymonthday
+        .iter()
+        .any(|monthday| *monthday == from_start || *monthday == from_end)
+}
+
+fn matches_month(rrule: &FCRRule, day: NaiveDate) -> bool {
+    rrule.bymonth.is_empty() || rrule.bymonth.contains(&day.month())
+}
+
+fn weekday_from_code(code: &str) -> Option<Weekday> {
+    match code {
+        "mo" => Some(Weekday::Mon),
+        "tu" => Some(Weekday::Tue),
+        "we" => Some(Weekday::Wed),
+        "th" => Some(Weekday::Thu),
+        "fr" => Some(Weekday::Fri),
+        "sa" => Some(Weekday::Sat),
+        "su" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nth_weekdays() {
+        let rrule: RRule = "RRULE:FREQ=MONTHLY;BYDAY=2TH,4TH".parse().unwrap();
+        assert_eq!(rrule.freq, "monthly");
+        assert_eq!(rrule.interval, 1);
+        assert_eq!(rrule.byweekday, ["th"]);
+        assert_eq!(rrule.bysetpos, [2, 4]);
+    }
+
+    #[test]
+    fn reject_ordinals_of_several_weekdays() {
+        // One occurrence a month with BYSETPOS, not two
+        let error = "FREQ=MONTHLY;BYDAY=1MO,1FR".parse::<RRule>().unwrap_err();
+        assert!(error.contains("single weekday"), "{}", error);
+        // Positions 2 and 4 of the Tuesdays and Thursdays together
+        assert!("FREQ=MONTHLY;BYDAY=2TU,4TU,2TH,4TH"
+            .parse::<RRule>()
+            .is_err());
+        let rrule: RRule = "FREQ=MONTHLY;BYDAY=-1FR".parse().unwrap();
+        assert_eq!(rrule.byweekday, ["fr"]);
+        assert_eq!(rrule.bysetpos, [-1]);
+    }
+
+    #[test]
+    fn parse_last_weekday_of_month() {
+        let rrule: RRule = "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;UNTIL=20221231"
+            .parse()
+            .unwrap();
+        assert_eq!(rrule.byweekday, ["mo", "tu", "we", "th", "fr"]);
+        assert_eq!(rrule.bysetpos, [-1]);
+        assert_eq!(
+            rrule.until,
+            Some(FCStart::Day(NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()))
+        );
+    }
+
+    fn datetime(month: u32, day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, month, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn occurrences_of_nth_weekdays() {
+        let rrule: RRule = "FREQ=MONTHLY;BYDAY=2TH,4TH".parse().unwrap();
+        let rrule = rrule.to_fc_rrule(FCStart::DateTime(datetime(8, 11, 15)));
+        assert_eq!(
+            occurrences(&rrule, datetime(8, 1, 0), datetime(10, 1, 0)).unwrap(),
+            [
+                datetime(8, 11, 15),
+                datetime(8, 25, 15),
+                datetime(9, 8, 15),
+                datetime(9, 22, 15)
+            ]
+        );
+    }
+
+    #[test]
+    fn occurrences_of_last_weekday_of_month() {
+        let rrule: RRule = "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;COUNT=3"
+            .parse()
+            .unwrap();
+        let rrule = rrule.to_fc_rrule(FCStart::DateTime(datetime(7, 1, 9)));
+        // July 31st 2022 is a Sunday, December 30th a Friday
+        assert_eq!(
+            occurrences(&rrule, datetime(1, 1, 0), datetime(12, 31, 0)).unwrap(),
+            [datetime(7, 29, 9), datetime(8, 31, 9), datetime(9, 30, 9)]
+        );
+    }
+
+    #[test]
+    fn keep_the_last_day_of_a_date_until() {
+        let rrule: RRule = "FREQ=DAILY;UNTIL=20220803".parse().unwrap();
+        let rrule = rrule.to_fc_rrule(FCStart::DateTime(datetime(8, 1, 15)));
+        assert_eq!(
+            rrule.until,
+            Some(FCStart::DateTime(
+                datetime(8, 3, 23) + Duration::seconds(3599)
+            ))
+        );
+        assert_eq!(
+            occurrences(&rrule, datetime(8, 1, 0), datetime(9, 1, 0))
+                .unwrap()
+                .last(),
+            Some(&datetime(8, 3, 15))
+        );
+        let rrule: RRule = "FREQ=DAILY;UNTIL=20220803".parse().unwrap();
+        let rrule = rrule.to_fc_rrule(FCStart::Day(NaiveDate::from_ymd_opt(2022, 8, 1).unwrap()));
+        assert_eq!(
+            rrule.until,
+            Some(FCStart::Day(NaiveDate::from_ymd_opt(2022, 8, 3).unwrap()))
+        );
+    }
+
+    #[test]
+    fn expand_without_exception_dates() {
+        let mut rrule = FCRRule::new(FCStart::DateTime(datetime(7, 27, 17)), "daily", 1);
+        rrule.until = Some(FCStart::Day(NaiveDate::from_ymd_opt(2022, 7, 30).unwrap()));
+        let event = FCEvent::builder("Walk", FCStart::DateTime(datetime(7, 27, 17)))
+            .id(Some("walk".to_string()))
+            .end(Some(datetime(7, 27, 18)))
+            .rrule(Some(rrule))
+            .exdate(vec![FCStart::DateTime(datetime(7, 28, 17))])
+            .build();
+        let (expanded, diagnostics) =
+            expand_recurring_events(vec![event], datetime(7, 1, 0), datetime(8, 1, 0));
+        assert!(diagnostics.is_empty());
+        let starts: Vec<String> = expanded.iter().map(|e| e.start.to_string()).collect();
+        assert_eq!(
+            starts,
+            [
+                "2022-07-27T17:00:00",
+                "2022-07-29T17:00:00",
+                "2022-07-30T17:00:00"
+            ]
+        );
+        assert_eq!(expanded[1].end, Some(datetime(7, 29, 18)));
+        assert_eq!(expanded[1].id.as_deref(), Some("walk@2022-07-29T17:00:00"));
+        assert!(expanded[1].rrule.is_none());
+    }
+
+    #[test]
+    fn report_rules_which_do_not_reach_the_window() {
+        let rrule = FCRRule::new(FCStart::DateTime(datetime(1, 1, 0)), "secondly", 1);
+        let event = FCEvent::builder("Tick", FCStart::DateTime(datetime(1, 1, 0)))
+            .rrule(Some(rrule))
+            .file_path(Some("clock.org".to_string()))
+            .build();
+        let (expanded, diagnostics) =
+            expand_recurring_events(vec![event], datetime(12, 1, 0), datetime(12, 2, 0));
+        assert_eq!(expanded.len(), 1);
+        assert!(expanded[0].rrule.is_some());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file_path, "clock.org");
+    }
+
+    #[test]
+    fn expand_around_today() {
+        let today = NaiveDate::from_ymd_opt(2022, 8, 10).unwrap();
+        let options = ExpandOptions {
+            days_before: 9,
+            days_after: 21,
+        };
+        assert_eq!(
+            options.window(today),
+            (datetime(8, 1, 0), datetime(9, 1, 0))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        assert!("INTERVAL=2".parse::<RRule>().is_err());
+        assert!("FREQ=FORTNIGHTLY".parse::<RRule>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=XX".parse::<RRule>().is_err());
+        assert!("FREQ=DAILY;COUNT=3;UNTIL=20221231"
+            .parse::<RRule>()
+            .is_err());
+        assert!("FREQ=MONTHLY;BYDAY=1MO,2TU".parse::<RRule>().is_err());
+        // The 2nd Thursday among the 8th to the 14th would be none
+        assert!("FREQ=MONTHLY;BYMONTHDAY=8,9,10,11,12,13,14;BYDAY=2TH"
+            .parse::<RRule>()
+            .is_err());
+        assert!("FREQ=YEARLY;BYMONTH=3,9;BYDAY=2SU"
+            .parse::<RRule>()
+            .is_err());
+        assert!("FREQ=MONTHLY;BYMONTHDAY=32".parse::<RRule>().is_err());
+        assert!("FREQ=DAILY;BYHOUR=9".parse::<RRule>().is_err());
+    }
+}
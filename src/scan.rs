@@ -0,0 +1,322 @@
+//! Conversion of many Org files at once: in parallel, and cached on disk.
+//!
+//! The files are shared out among threads, but the events keep the order of
+//! the files. With a cache file, the events of each file are stored with the
+//! size, the modification time and the hash of the text of the file and of
+//! its included files; on the next run, a file whose size and time, or else
+//! text, did not change is not converted again. The cache is dropped when
+//! the options change, and every day when they leave out events by date or
+//! expand the repeating ones.
+use crate::config::ClockEvents;
+use crate::diagnostic::Diagnostic;
+use crate::fullcalendar::FCEvent;
+use crate::hash::{stable_hash, stable_hash_all};
+use crate::include::expand_includes;
+use crate::options::ConvertOptions;
+use crate::parse::expanded_to_fc_vec;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+/// Changed when the events of the same files and options change
+const CACHE_VERSION: &str = "1";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Number of threads, that of the CPU cores when None
+    pub threads: Option<usize>,
+    /// Cache file, no cache when None
+    pub cache: Option<String>,
+}
+
+/// A file an entry was made from, as it was
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedFile {
+    path: String,
+    size: u64,
+    /// Modification time, in nanoseconds from the Unix epoch
+    modified: String,
+    hash: u64,
+}
+
+impl CachedFile {
+    fn new(path: &str, hash: u64) -> Option<CachedFile> {
+        let (size, modified) = file_stamp(path)?;
+        Some(CachedFile {
+            path: path.to_string(),
+            size,
+            modified,
+            hash,
+        })
+    }
+
+    /// The file as it is now, None when it changed
+    fn refresh(&self) -> Option<CachedFile> {
+        let (size, modified) = file_stamp(&self.path)?;
+        if size == self.size && modified == self.modified {
+            return Some(self.clone());
+        }
+        let text = fs::read_to_string(&self.path).ok()?;
+        (stable_hash(&text) == self.hash).then(|| CachedFile {
+            size,
+            modified,
+            ..self.clone()
+        })
+    }
+}
+
+/// The events of a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    /// The file and its included files
+    files: Vec<CachedFile>,
+    events: Vec<FCEvent>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Entry {
+    /// The entry with its files as they are now, None when one changed
+    fn refresh(&self) -> Option<Entry> {
+        let files = self
+            .files
+            .iter()
+            .map(CachedFile::refresh)
+            .collect::<Option<Vec<CachedFile>>>()?;
+        Some(Entry {
+            files,
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    /// Hash of the options the events were made with
+    key: u64,
+    /// Per path of the files
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Cache {
+    /// The cache in a file, empty when it is missing, unreadable or made
+    /// with other options
+    fn load(path: &str, key: u64) -> Cache {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Cache>(&json).ok())
+            .filter(|cache| cache.key == key)
+            .unwrap_or(Cache {
+                key,
+                entries: BTreeMap::new(),
+            })
+    }
+
+    /// Write the cache, through a temporary file so that it is never half
+    /// written
+    fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|error| error.to_string())?;
+        let temporary = format!("{}.tmp", path);
+        fs::write(&temporary, json)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|error| format!("{}: {}", path, error))
+    }
+}
+
+/// Size and modification time of a file
+fn file_stamp(path: &str) -> Option<(u64, String)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos().to_string()))
+}
+
+/// What the events of the files depend on, besides the files
+fn cache_key(options: &ConvertOptions, clocks: ClockEvents) -> u64 {
+    let windowed = options.ignore_before_days != 0
+        || options.ignore_after_days != 0
+        || options.expand_recurring.is_some();
+    let today = if windowed {
+        Local::now().date_naive().to_string()
+    } else {
+        String::new()
+    };
+    stable_hash_all(&[
+        CACHE_VERSION,
+        &serde_json::to_string(options).unwrap_or_default(),
+        &serde_json::to_string(&clocks).unwrap_or_default(),
+        &today,
+    ])
+}
+
+/// Convert a file, or take its events from the cache
+fn convert(
+    path: &Path,
+    cached: Option<&Entry>,
+    options: &ConvertOptions,
+    clocks: ClockEvents,
+) -> Result<Entry, String> {
+    if let Some(entry) = cached.and_then(Entry::refresh) {
+        return Ok(entry);
+    }
+    let file_path = path.to_string_lossy();
+    let expanded = expand_includes(&file_path)?;
+    let (events, diagnostics) = expanded_to_fc_vec(&expanded, &file_path, options, clocks);
+    let files = expanded
+        .sources()
+        .into_iter()
+        .filter_map(|(path, hash)| CachedFile::new(path, hash))
+        .collect();
+    Ok(Entry {
+        files,
+        events,
+        diagnostics,
+    })
+}
+
+/// Make a FC Event vector from Org files, in their order, and the problems
+/// found
+pub fn scan(
+    paths: &[PathBuf],
+    options: &ConvertOptions,
+    clocks: ClockEvents,
+    scan_options: &ScanOptions,
+) -> Result<(Vec<FCEvent>, Vec<Diagnostic>), String> {
+    let key = cache_key(options, clocks);
+    let cache = match &scan_options.cache {
+        Some(path) => Cache::load(path, key),
+        None => Cache::default(),
+    };
+    let threads = scan_options
+        .threads
+        .or_else(|| thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .clamp(1, paths.len().max(1));
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<Result<Entry, String>>> = vec![None; paths.len()];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut converted: Vec<(usize, Result<Entry, String>)> = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let path = match paths.get(index) {
+                            Some(path) => path,
+                            None => return converted,
+                        };
+                        let cached = cache.entries.get(path.to_string_lossy().as_ref());
+                        converted.push((index, convert(path, cached, options, clocks)));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            // A panic is a bug: pass it on as it is
+            let converted = worker
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic));
+            for (index, result) in converted {
+                results[index] = Some(result);
+            }
+        }
+    });
+
+    let mut fullcalendar_events: Vec<FCEvent> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut entries: BTreeMap<String, Entry> = BTreeMap::new();
+    for (path, result) in paths.iter().zip(results) {
+        let entry = result.unwrap_or_else(|| Err(format!("{}: not converted", path.display())))?;
+        fullcalendar_events.extend(entry.events.iter().cloned());
+        diagnostics.extend(entry.diagnostics.iter().cloned());
+        entries.insert(path.to_string_lossy().into_owned(), entry);
+    }
+    if let Some(path) = &scan_options.cache {
+        Cache { key, entries }.save(path)?;
+    }
+    Ok((fullcalendar_events, diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Put an event in the cache entry of a file, to tell whether the file
+    /// is converted again
+    fn mark_cached(cache: &Path, file: &Path) {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(cache).unwrap()).unwrap();
+        let event = serde_json::json!({"id": "cached", "title": "Cached", "start": "2022-08-01"});
+        json["entries"][file.to_string_lossy().as_ref()]["events"]
+            .as_array_mut()
+            .unwrap()
+            .push(event);
+        fs::write(cache, json.to_string()).unwrap();
+    }
+
+    fn cached_ids(events: &[FCEvent]) -> usize {
+        events
+            .iter()
+            .filter(|event| event.id.as_deref() == Some("cached"))
+            .count()
+    }
+
+    #[test]
+    fn skip_unchanged_files() {
+        let task = "* TODO Task\nSCHEDULED: <2022-08-01 Mon>\n";
+        let dir = TempDir::new("scan", &[("b.org", task), ("a.org", task)]);
+        let files = [dir.join("b.org"), dir.join("a.org")];
+        let cache = dir.join("cache.json");
+        let scan_options = ScanOptions {
+            threads: Some(2),
+            cache: Some(cache.to_string_lossy().into_owned()),
+        };
+        let options = ConvertOptions::default();
+        let scan = || scan(&files, &options, ClockEvents::Include, &scan_options).unwrap();
+
+        let (first, _) = scan();
+        mark_cached(&cache, &files[0]);
+        let (events, _) = scan();
+        assert_eq!(cached_ids(&events), 1);
+        // After the events of the first file, before those of the second
+        assert_eq!(
+            events
+                .iter()
+                .position(|event| event.id.as_deref() == Some("cached")),
+            Some(first.len() / 2)
+        );
+
+        // Same text, new time: still cached
+        fs::write(&files[0], task).unwrap();
+        assert_eq!(cached_ids(&scan().0), 1);
+
+        fs::write(&files[0], "* DONE Task\n").unwrap();
+        assert_eq!(cached_ids(&scan().0), 0);
+
+        // Other options: all converted again
+        mark_cached(&cache, &files[1]);
+        let options = ConvertOptions::new(0, 30);
+        let (events, _) =
+            super::scan(&files, &options, ClockEvents::Include, &scan_options).unwrap();
+        assert_eq!(cached_ids(&events), 0);
+    }
+
+    #[test]
+    fn report_missing_files() {
+        let error = scan(
+            &[PathBuf::from("/nonexistent/notes.org")],
+            &ConvertOptions::default(),
+            ClockEvents::Exclude,
+            &ScanOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.starts_with("/nonexistent/notes.org"), "{}", error);
+    }
+}
@@ -0,0 +1,129 @@
+//! In-buffer settings of an Org file: the `#+KEYWORD: value` lines which
+//! apply to the whole file, wherever they are.
+use crate::priority::Priorities;
+use crate::tags::{merge_tags, parse_tags};
+use crate::template::Template;
+use crate::todo::{is_todo_setting, TodoKeywords};
+use orgize::elements;
+use orgize::{Event, Org};
+
+/// Tags which exclude a subtree when the file has no `#+EXCLUDE_TAGS`
+pub const DEFAULT_EXCLUDE_TAGS: &[&str] = &["noexport"];
+
+/// Tag of archived subtrees
+pub const ARCHIVE_TAG: &str = "ARCHIVE";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileSettings {
+    /// `#+FILETAGS`
+    pub file_tags: Vec<String>,
+    /// `#+EXCLUDE_TAGS`, None without the keyword
+    pub exclude_tags: Option<Vec<String>>,
+    /// `#+PROPERTY: KEY value`, the defaults of properties
+    pub properties: Vec<(String, String)>,
+    /// `#+TODO:` sequences, None without them
+    pub todo_keywords: Option<TodoKeywords>,
+    /// `#+PRIORITIES`, None without it
+    pub priorities: Option<Priorities>,
+    /// The first `#+CATEGORY`
+    pub category: Option<String>,
+    /// `#+FC_TITLE_TEMPLATE`, see [`crate::template`]
+    pub title_template: Option<Template>,
+    /// `#+FC_DESCRIPTION_TEMPLATE`
+    pub description_template: Option<Template>,
+    /// `#+LANGUAGE`
+    pub language: Option<String>,
+}
+
+impl FileSettings {
+    pub fn from_org(org: &Org) -> FileSettings {
+        let mut settings = FileSettings::default();
+        for event in org.iter() {
+            if let Event::Start(elements::Element::Keyword(keyword)) = event {
+                settings.set(&keyword.key, &keyword.value);
+            }
+        }
+        settings
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        if is_todo_setting(key) {
+            self.todo_keywords
+                .get_or_insert_with(|| TodoKeywords {
+                    todo: Vec::new(),
+                    done: Vec::new(),
+                })
+                .add_sequence(value);
+            return;
+        }
+        let tags = parse_tags(value);
+        let tags = tags.iter().map(|tag| tag.as_str());
+        match key.to_uppercase().as_str() {
+            "FILETAGS" => merge_tags(&mut self.file_tags, tags),
+            "EXCLUDE_TAGS" => merge_tags(self.exclude_tags.get_or_insert_with(Vec::new), tags),
+            "PROPERTY" => self.set_property(value),
+            "CATEGORY" if self.category.is_none() && !value.trim().is_empty() => {
+                self.category = Some(value.trim().to_string())
+            }
+            "PRIORITIES" => self.priorities = Priorities::parse(value).or(self.priorities),
+            "LANGUAGE" if !value.trim().is_empty() => {
+                self.language = Some(value.trim().to_string())
+            }
+            "FC_TITLE_TEMPLATE" => self.title_template = Some(Template::parse(value.trim())),
+            "FC_DESCRIPTION_TEMPLATE" => {
+                self.description_template = Some(Template::parse(value.trim()))
+            }
+            _ => {}
+        }
+    }
+
+    /// `KEY value`, or `KEY+ value` to add to the value
+    fn set_property(&mut self, value: &str) {
+        let value = value.trim();
+        let (key, value) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+        let value = value.trim();
+        if key.is_empty() {
+            return;
+        }
+        if let Some(key) = key.strip_suffix('+') {
+            if let Some((_, known)) = self.properties.iter_mut().find(|(k, _)| k == key) {
+                known.push(' ');
+                known.push_str(value);
+                return;
+            }
+            self.properties.push((key.to_string(), value.to_string()));
+            return;
+        }
+        self.properties.retain(|(k, _)| k != key);
+        self.properties.push((key.to_string(), value.to_string()));
+    }
+
+    /// `#+EXCLUDE_TAGS`, or `noexport` like in Org's export
+    pub fn exclude_tags(&self) -> Vec<&str> {
+        match &self.exclude_tags {
+            Some(tags) => tags.iter().map(|tag| tag.as_str()).collect(),
+            None => DEFAULT_EXCLUDE_TAGS.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_property_defaults() {
+        let mut settings = FileSettings::default();
+        settings.set("PROPERTY", "FC_BG_COLOR  #ff0000");
+        settings.set("property", "LOCATION Office");
+        settings.set("PROPERTY", "LOCATION+ 2nd floor");
+        settings.set("PROPERTY", "FC_BG_COLOR blue");
+        assert_eq!(
+            settings.properties,
+            [
+                ("LOCATION".to_string(), "Office 2nd floor".to_string()),
+                ("FC_BG_COLOR".to_string(), "blue".to_string()),
+            ]
+        );
+    }
+}
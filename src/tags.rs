@@ -0,0 +1,65 @@
+//! Org tags: the headline's own tags, those inherited from its ancestors and
+//! the file's `#+FILETAGS`. They are output in `extendedProps.tags` and as
+//! `tag-` CSS classes, e.g. `:@home:` becomes `tag-home`.
+
+/// Prefix of the CSS classes made from tags
+pub const TAG_CLASS_PREFIX: &str = "tag-";
+
+/// Tags of a `#+FILETAGS` value, written `:work:urgent:` or `work urgent`
+pub fn parse_tags(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+/// Add tags not yet in `tags`, keeping the first occurrence's position
+pub fn merge_tags<'t>(tags: &mut Vec<String>, more: impl IntoIterator<Item = &'t str>) {
+    for tag in more {
+        if !tags.iter().any(|known| known == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+}
+
+/// CSS class of a tag: lower case, with the characters which are not
+/// letters, digits or `_` (`@`, `#`, `%` ...) replaced by `-`
+pub fn tag_class_name(tag: &str) -> String {
+    let mut name = String::from(TAG_CLASS_PREFIX);
+    for c in tag.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' {
+            name.push(c);
+        } else if !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    name.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_file_tags() {
+        assert_eq!(parse_tags(":work:urgent:"), ["work", "urgent"]);
+        assert_eq!(parse_tags("work urgent"), ["work", "urgent"]);
+        assert!(parse_tags("  ").is_empty());
+    }
+
+    #[test]
+    fn merge_without_duplicates() {
+        let mut tags = vec!["work".to_string(), "home".to_string()];
+        merge_tags(&mut tags, ["home", "urgent"]);
+        assert_eq!(tags, ["work", "home", "urgent"]);
+    }
+
+    #[test]
+    fn sanitize_class_names() {
+        assert_eq!(tag_class_name("work"), "tag-work");
+        assert_eq!(tag_class_name("@Home"), "tag-home");
+        assert_eq!(tag_class_name("client_A#1"), "tag-client_a-1");
+        assert_eq!(tag_class_name("Réunion"), "tag-réunion");
+    }
+}
@@ -0,0 +1,159 @@
+//! Templates of the titles and descriptions of events, e.g.
+//! `Owner: {OWNER} / Room: {LOCATION} / {body}`.
+//!
+//! A `{field}` in lower case is one of
+//!
+//! | Field          | Value                                                  |
+//! |----------------|--------------------------------------------------------|
+//! | `title`        | title words, rendered as set in [`crate::markup`]      |
+//! | `prefix`       | `DL: `, `SCL: ` or nothing for CLOCK lines             |
+//! | `keyword`      | TODO keyword                                           |
+//! | `priority`     | priority, e.g. `A`                                     |
+//! | `tags`         | tags, inherited ones included, separated by `, `       |
+//! | `outline_path` | titles of the ancestors and the headline, separated by ` / ` |
+//! | `breadcrumb`   | the same, separated as set in [`crate::outline::MetadataOptions`] |
+//! | `file`         | file name                                              |
+//! | `category`     | category, see [`crate::category`]                      |
+//! | `start`, `end` | `2022-08-01` or `2022-08-01 10:00`                     |
+//! | `start_time`, `end_time` | `10:00`                                      |
+//! | `duration`     | `1:30`                                                 |
+//! | `body`         | body, see [`crate::body`]                              |
+//! | `description`  | `DESCRIPTION` properties                               |
+//!
+//! any other `{FIELD}` is the value of a property, e.g. `{LOCATION}`.
+//! A field without a value is left empty. `{{` and `}}` are braces.
+//!
+//! Values are escaped for HTML in the templates of HTML output, i.e. the
+//! `titleHtml` of [`crate::markup::TitleMarkup::Html`] and the descriptions,
+//! unless they are plain text. The template text itself is written as it is.
+//!
+//! Templates are set for all files by the options, and per file by
+//! `#+FC_TITLE_TEMPLATE:` and `#+FC_DESCRIPTION_TEMPLATE:`.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A parsed template, read and written as its text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Template {
+    text: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+impl Template {
+    /// A `{` without its `}` is text
+    pub fn parse(text: &str) -> Template {
+        let mut parts: Vec<Part> = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                literal.push(c);
+                rest = &rest[2..];
+                continue;
+            }
+            if c == '{' {
+                let field = rest[1..]
+                    .find(['{', '}'])
+                    .filter(|&end| rest[1 + end..].starts_with('}'))
+                    .map(|end| rest[1..1 + end].trim())
+                    .filter(|field| !field.is_empty());
+                if let Some(field) = field {
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field.to_string()));
+                    rest = &rest[rest.find('}').unwrap_or_default() + 1..];
+                    continue;
+                }
+            }
+            literal.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Template {
+            text: text.to_string(),
+            parts,
+        }
+    }
+
+    /// Does the template have the field?
+    pub fn uses(&self, field: &str) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(name) if name == field))
+    }
+
+    /// Fill in the fields with their values, already escaped
+    pub fn render(&self, mut value: impl FnMut(&str) -> Option<String>) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Field(field) => rendered.push_str(&value(field).unwrap_or_default()),
+            }
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl From<String> for Template {
+    fn from(text: String) -> Template {
+        Template::parse(&text)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> String {
+        template.text
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateOptions {
+    /// Title of the events instead of the prefix, the keyword and the title
+    pub title: Option<Template>,
+    /// Description of the events instead of the body or the `DESCRIPTION`
+    /// properties
+    pub description: Option<Template>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_fields() {
+        let template = Template::parse("Owner: {OWNER} / Room: { LOCATION } / {body}");
+        assert!(template.uses("LOCATION"));
+        assert!(!template.uses("title"));
+        let rendered = template.render(|field| match field {
+            "OWNER" => Some("Ann".to_string()),
+            "body" => Some("<p>Notes</p>".to_string()),
+            _ => None,
+        });
+        assert_eq!(rendered, "Owner: Ann / Room:  / <p>Notes</p>");
+    }
+
+    #[test]
+    fn braces_as_text() {
+        let template = Template::parse("{{x}} {} {open {title}");
+        assert_eq!(template.render(|_| Some("T".to_string())), "{x} {} {open T");
+        assert_eq!(template.to_string(), "{{x}} {} {open {title}");
+    }
+}
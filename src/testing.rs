@@ -0,0 +1,49 @@
+//! Helpers of the tests.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A directory of files in the temporary directory, removed when dropped,
+/// also when a test fails
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// A directory with files, given by their paths in it and their contents
+    pub fn new(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let root =
+            std::env::temp_dir().join(format!("org2fullcalendar-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let dir = TempDir(root);
+        for (path, contents) in files {
+            dir.write(path, contents);
+        }
+        dir
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path of a file in the directory
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Path of a file in the directory, as a string
+    pub fn path(&self, path: &str) -> String {
+        self.join(path).to_string_lossy().into_owned()
+    }
+
+    /// Write a file, and the directories it is in
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
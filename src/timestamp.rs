@@ -5,10 +5,12 @@ pub fn datetime_duration(
     start: &orgize::elements::Datetime,
     end: &orgize::elements::Datetime,
 ) -> String {
-    let end_daytime: NaiveDateTime = end.into();
-    let dur_sec = end_daytime
-        .signed_duration_since(start.into())
-        .num_seconds();
+    naive_datetime_duration(start.into(), end.into())
+}
+
+/// Calcurate duration and format as min:sec:00
+pub fn naive_datetime_duration(start: NaiveDateTime, end: NaiveDateTime) -> String {
+    let dur_sec = end.signed_duration_since(start).num_seconds();
     let hour: i64 = dur_sec / 3600;
     let min: i64 = (dur_sec - hour * 3600) / 60;
     format!("{}:{:02}:00", hour, min)
@@ -61,3 +63,24 @@ fn date_is_n_days_after_now(timestamp: &orgize::elements::Datetime, ndays: i64)
     }
     false
 }
+
+/// Format a date as Org does, without the brackets: `2022-08-01 Mon 10:00`
+pub fn format_datetime(datetime: &orgize::elements::Datetime) -> String {
+    let mut text = format!(
+        "{:04}-{:02}-{:02}",
+        datetime.year, datetime.month, datetime.day
+    );
+    if !datetime.dayname.is_empty() {
+        text.push(' ');
+        text.push_str(&datetime.dayname);
+    }
+    if let (Some(hour), Some(minute)) = (datetime.hour, datetime.minute) {
+        text.push_str(&format!(" {:02}:{:02}", hour, minute));
+    }
+    text
+}
+
+/// Format a date and time for people: `2022-08-01 10:00`
+pub fn format_naive_datetime(datetime: NaiveDateTime) -> String {
+    datetime.format("%Y-%m-%d %H:%M").to_string()
+}
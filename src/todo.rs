@@ -0,0 +1,133 @@
+//! TODO keyword sequences, as set by `#+TODO:`, `#+SEQ_TODO:` and
+//! `#+TYP_TODO:` lines or, without them, by [`crate::options::ConvertOptions`].
+//!
+//! orgize only recognizes the keywords it is told about, so a text should be
+//! parsed with [`parse_org`] for `NEXT` in `* NEXT Call` to be a keyword.
+use crate::options::ConvertOptions;
+use orgize::{Org, ParseConfig};
+use serde::{Deserialize, Serialize};
+
+/// Keywords of the lines setting TODO sequences
+const TODO_SETTINGS: &[&str] = &["TODO", "SEQ_TODO", "TYP_TODO"];
+
+/// States of a headline: not done and done
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoKeywords {
+    pub todo: Vec<String>,
+    pub done: Vec<String>,
+}
+
+impl Default for TodoKeywords {
+    /// `TODO | DONE`, like Org
+    fn default() -> TodoKeywords {
+        TodoKeywords {
+            todo: vec!["TODO".to_string()],
+            done: vec!["DONE".to_string()],
+        }
+    }
+}
+
+impl TodoKeywords {
+    /// Add a sequence like `TODO NEXT(n) WAIT(w@/!) | DONE(d) CANCELLED(c@)`.
+    /// Without `|`, the last keyword is the done state.
+    pub fn add_sequence(&mut self, value: &str) {
+        let keyword = |word: &str| word.split('(').next().unwrap_or_default().to_string();
+        let (todo, done): (Vec<String>, Vec<String>) = match value.split_once('|') {
+            Some((todo, done)) => (
+                todo.split_whitespace().map(keyword).collect(),
+                done.split_whitespace().map(keyword).collect(),
+            ),
+            None => {
+                let mut todo: Vec<String> = value.split_whitespace().map(keyword).collect();
+                let done = todo.pop().into_iter().collect();
+                (todo, done)
+            }
+        };
+        for (keywords, more) in [(&mut self.todo, todo), (&mut self.done, done)] {
+            for keyword in more {
+                if !keyword.is_empty() && !keywords.contains(&keyword) {
+                    keywords.push(keyword);
+                }
+            }
+        }
+    }
+
+    /// Keywords set by the lines of a text, None without such lines
+    pub fn from_text(text: &str) -> Option<TodoKeywords> {
+        let mut keywords: Option<TodoKeywords> = None;
+        for line in text.lines() {
+            let line = line.trim_start();
+            let setting = match line.strip_prefix("#+") {
+                Some(setting) => setting,
+                None => continue,
+            };
+            if let Some((key, value)) = setting.split_once(':') {
+                if is_todo_setting(key) {
+                    keywords
+                        .get_or_insert_with(|| TodoKeywords {
+                            todo: Vec::new(),
+                            done: Vec::new(),
+                        })
+                        .add_sequence(value);
+                }
+            }
+        }
+        keywords
+    }
+
+    pub fn is_done(&self, keyword: &str) -> bool {
+        self.done.iter().any(|done| done == keyword)
+    }
+
+    pub fn to_parse_config(&self) -> ParseConfig {
+        ParseConfig {
+            todo_keywords: (self.todo.clone(), self.done.clone()),
+        }
+    }
+}
+
+/// Is it the keyword of a line setting a TODO sequence?
+pub fn is_todo_setting(key: &str) -> bool {
+    TODO_SETTINGS
+        .iter()
+        .any(|setting| setting.eq_ignore_ascii_case(key))
+}
+
+/// Parse a text with its TODO keywords, or those of the options
+pub fn parse_org<'a>(text: &'a str, options: &ConvertOptions) -> Org<'a> {
+    let keywords = TodoKeywords::from_text(text);
+    let keywords = keywords.as_ref().unwrap_or(&options.todo_keywords);
+    Org::parse_custom(text, &keywords.to_parse_config())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_sequences() {
+        let keywords = TodoKeywords::from_text(
+            "#+TITLE: Tasks\n#+TODO: TODO NEXT(n) WAIT(w@/!) | DONE(d) CANCELLED(c@)\n#+seq_todo: REPORT BUG KNOWNCAUSE FIXED\n* TODO Task\n",
+        )
+        .unwrap();
+        assert_eq!(
+            keywords.todo,
+            strings(&["TODO", "NEXT", "WAIT", "REPORT", "BUG", "KNOWNCAUSE"])
+        );
+        assert_eq!(keywords.done, strings(&["DONE", "CANCELLED", "FIXED"]));
+        assert!(keywords.is_done("CANCELLED"));
+        assert!(!keywords.is_done("WAIT"));
+    }
+
+    #[test]
+    fn no_sequence_without_settings() {
+        assert_eq!(
+            TodoKeywords::from_text("* TODO Task\n#+TITLE: Tasks\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Org files of directory trees.
+//!
+//! The directories are walked down recursively, in the order of the names.
+//! A file is read when it matches an `include` pattern (`*.org`, and
+//! `*.org_archive` with `archives`) and no `exclude` pattern; a directory is
+//! skipped when it matches an `exclude` pattern. Patterns with a `/` are
+//! matched against the path from the root, the others against the name, e.g.
+//! `drafts` or `projects/*/old/**`.
+//!
+//! Ignore files (`.gitignore` and `.orgignore`) are read as in Git: `#`
+//! comments, `!` to include again, a trailing `/` for directories only, and
+//! a leading or inner `/` for paths from the ignore file's directory. They
+//! apply to their directory and its subdirectories.
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkOptions {
+    /// Patterns of the files read, `*.org` when empty
+    pub include: Vec<String>,
+    /// Patterns of the files and directories left out
+    pub exclude: Vec<String>,
+    /// Names of the ignore files
+    pub ignore_files: Vec<String>,
+    /// Follow symbolic links to directories and files
+    pub follow_symlinks: bool,
+    /// Read hidden files and directories, whose names start with `.`
+    pub hidden: bool,
+    /// Levels of directories walked down below the roots, all when None
+    pub max_depth: Option<usize>,
+    /// Also read `*.org_archive` files
+    pub archives: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ignore_files: vec![".gitignore".to_string(), ".orgignore".to_string()],
+            follow_symlinks: false,
+            hidden: false,
+            max_depth: None,
+            archives: false,
+        }
+    }
+}
+
+/// A pattern matched against a name, or against a path from a directory
+#[derive(Debug, Clone)]
+struct PathPattern {
+    pattern: Pattern,
+    /// Match the path from `base`, not the name
+    anchored: bool,
+    /// Directory of the pattern, relative to the root
+    base: PathBuf,
+}
+
+impl PathPattern {
+    fn new(text: &str, base: &Path) -> Result<PathPattern, String> {
+        let anchored = text.contains('/');
+        let text = text.strip_prefix('/').unwrap_or(text);
+        let pattern = Pattern::new(text)
+            .map_err(|error| format!("invalid pattern \"{}\": {}", text, error))?;
+        Ok(PathPattern {
+            pattern,
+            anchored,
+            base: base.to_path_buf(),
+        })
+    }
+
+    /// Does the pattern match a path relative to the root?
+    fn matches(&self, relative: &Path) -> bool {
+        if self.anchored {
+            relative
+                .strip_prefix(&self.base)
+                .is_ok_and(|path| self.pattern.matches_path_with(path, MATCH_OPTIONS))
+        } else {
+            relative
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| self.pattern.matches_with(name, MATCH_OPTIONS))
+        }
+    }
+}
+
+/// A line of an ignore file
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    pattern: PathPattern,
+    /// `!`: include again
+    negated: bool,
+    /// Trailing `/`: directories only
+    dir_only: bool,
+}
+
+/// The rules of an ignore file in a directory, relative to the root
+fn read_ignore_file(path: &Path, base: &Path) -> Result<Vec<IgnoreRule>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let mut rules: Vec<IgnoreRule> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let pattern = PathPattern::new(line, base)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        rules.push(IgnoreRule {
+            pattern,
+            negated,
+            dir_only,
+        });
+    }
+    Ok(rules)
+}
+
+struct Walker<'a> {
+    options: &'a WalkOptions,
+    include: Vec<PathPattern>,
+    exclude: Vec<PathPattern>,
+    /// Directories visited, against symbolic link loops
+    visited: HashSet<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Walker<'_> {
+    fn walk(
+        &mut self,
+        root: &Path,
+        relative: &Path,
+        depth: usize,
+        rules: &[IgnoreRule],
+    ) -> Result<(), String> {
+        let dir = root.join(relative);
+        if let Ok(canonical) = dir.canonicalize() {
+            if !self.visited.insert(canonical) {
+                return Ok(());
+            }
+        }
+        let mut rules = rules.to_vec();
+        for name in &self.options.ignore_files {
+            rules.extend(read_ignore_file(&dir.join(name), relative)?);
+        }
+        let entries =
+            fs::read_dir(&dir).map_err(|error| format!("{}: {}", dir.display(), error))?;
+        let mut entries: Vec<fs::DirEntry> = entries
+            .collect::<Result<_, _>>()
+            .map_err(|error| format!("{}: {}", dir.display(), error))?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            if !self.options.hidden && name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let file_type = entry
+                .file_type()
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+            let is_dir = if file_type.is_symlink() {
+                if !self.options.follow_symlinks {
+                    continue;
+                }
+                // A broken link is neither a file nor a directory
+                match fs::metadata(&path) {
+                    Ok(metadata) => metadata.is_dir(),
+                    Err(_) => continue,
+                }
+            } else {
+                file_type.is_dir()
+            };
+            let relative = relative.join(&name);
+            if self.is_excluded(&relative, is_dir, &rules) {
+                continue;
+            }
+            if is_dir {
+                if self.options.max_depth.is_none_or(|max| depth < max) {
+                    self.walk(root, &relative, depth + 1, &rules)?;
+                }
+            } else if self
+                .include
+                .iter()
+                .any(|include| include.matches(&relative))
+            {
+                self.files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_excluded(&self, relative: &Path, is_dir: bool, rules: &[IgnoreRule]) -> bool {
+        if self.exclude.iter().any(|exclude| exclude.matches(relative)) {
+            return true;
+        }
+        let mut ignored = false;
+        for rule in rules {
+            if (is_dir || !rule.dir_only) && rule.pattern.matches(relative) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// The Org files of directories, sorted by directory and name
+pub fn org_files(
+    roots: &[impl AsRef<Path>],
+    options: &WalkOptions,
+) -> Result<Vec<PathBuf>, String> {
+    let patterns = |texts: &[String]| -> Result<Vec<PathPattern>, String> {
+        texts
+            .iter()
+            .map(|text| PathPattern::new(text, Path::new("")))
+            .collect()
+    };
+    let mut include = options.include.clone();
+    if include.is_empty() {
+        include.push("*.org".to_string());
+    }
+    if options.archives {
+        include.push("*.org_archive".to_string());
+    }
+    let mut walker = Walker {
+        options,
+        include: patterns(&include)?,
+        exclude: patterns(&options.exclude)?,
+        visited: HashSet::new(),
+        files: Vec::new(),
+    };
+    for root in roots {
+        walker.walk(root.as_ref(), Path::new(""), 0, &[])?;
+    }
+    Ok(walker.files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// The files of a directory, relative to it
+    fn files(dir: &TempDir, options: &WalkOptions) -> Vec<String> {
+        org_files(&[dir.root()], options)
+            .unwrap()
+            .iter()
+            .map(|path| {
+                let path = path.strip_prefix(dir.root()).unwrap();
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walk_recursively() {
+        let dir = TempDir::new(
+            "walk",
+            &[
+                ("inbox.org", ""),
+                ("notes.txt", ""),
+                ("done.org_archive", ""),
+                (".hidden/secret.org", ""),
+                ("projects/a/plan.org", ""),
+                ("projects/b/plan.org", ""),
+            ],
+        );
+        let mut options = WalkOptions::default();
+        assert_eq!(
+            files(&dir, &options),
+            ["inbox.org", "projects/a/plan.org", "projects/b/plan.org"]
+        );
+
+        options.max_depth = Some(0);
+        options.archives = true;
+        options.hidden = true;
+        assert_eq!(files(&dir, &options), ["done.org_archive", "inbox.org"]);
+
+        options.max_depth = Some(1);
+        options.exclude = vec!["projects".to_string()];
+        assert_eq!(
+            files(&dir, &options),
+            [".hidden/secret.org", "done.org_archive", "inbox.org"]
+        );
+    }
+
+    #[test]
+    fn read_ignore_files() {
+        let dir = TempDir::new(
+            "ignore",
+            &[
+                (".gitignore", "# Comment\n*.org\n!keep.org\nbuild/\n"),
+                ("keep.org", ""),
+                ("drop.org", ""),
+                ("build/keep.org", ""),
+                ("sub/.orgignore", "/local.org\n"),
+                ("sub/keep.org", ""),
+                ("sub/local.org", ""),
+                ("sub/deep/local.org", ""),
+            ],
+        );
+        let mut options = WalkOptions::default();
+        options.ignore_files.retain(|name| name != ".gitignore");
+        assert_eq!(
+            files(&dir, &options),
+            [
+                "build/keep.org",
+                "drop.org",
+                "keep.org",
+                "sub/deep/local.org",
+                "sub/keep.org"
+            ]
+        );
+
+        let options = WalkOptions::default();
+        assert_eq!(files(&dir, &options), ["keep.org", "sub/keep.org"]);
+    }
+
+    #[test]
+    fn match_patterns() {
+        let pattern = PathPattern::new("projects/*/old/**", Path::new("")).unwrap();
+        assert!(pattern.matches(Path::new("projects/a/old/b/c.org")));
+        assert!(!pattern.matches(Path::new("projects/a/b/old/c.org")));
+        let pattern = PathPattern::new("*.org", Path::new("sub")).unwrap();
+        assert!(pattern.matches(Path::new("sub/deep/x.org")));
+        assert!(PathPattern::new("[", Path::new("")).is_err());
+    }
+}